@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE users
+ALTER COLUMN password TYPE VARCHAR(255);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE refresh_tokens
+    ( id UUID NOT NULL PRIMARY KEY,
+      user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+      family_id UUID NOT NULL,
+      token_hash VARCHAR(64) NOT NULL UNIQUE,
+      created_on TIMESTAMP NOT NULL,
+      expires_at TIMESTAMP NOT NULL,
+      revoked BOOLEAN NOT NULL DEFAULT FALSE );
+
+
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens(family_id);
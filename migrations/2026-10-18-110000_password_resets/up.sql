@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE password_resets
+    ( id UUID NOT NULL PRIMARY KEY,
+      email VARCHAR(100) NOT NULL,
+      expires_at TIMESTAMP NOT NULL );
+
+
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE users ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'player' CHECK (role IN ('player', 'moderator', 'admin'));
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE players_data
+DROP COLUMN energy_updated_at;
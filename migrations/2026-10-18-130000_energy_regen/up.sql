@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE players_data ADD COLUMN energy_updated_at TIMESTAMP NOT NULL DEFAULT NOW();
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+-- no foreign key on user_id, the history outlives deleted accounts
+CREATE TABLE ledger_entries (
+    id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    resource VARCHAR(30) NOT NULL,
+    factory_id UUID,
+    delta INTEGER NOT NULL,
+    balance_after INTEGER NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    reason VARCHAR(200) NOT NULL,
+    created_on TIMESTAMP NOT NULL
+);
+
+CREATE INDEX ledger_entries_user_id_created_on ON ledger_entries (user_id, created_on DESC);
+
+CREATE FUNCTION ledger_entries_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'ledger_entries is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ledger_entries_append_only
+BEFORE UPDATE OR DELETE ON ledger_entries
+FOR EACH ROW EXECUTE PROCEDURE ledger_entries_append_only();
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE player_factories ADD COLUMN produced_at TIMESTAMP NOT NULL DEFAULT NOW();
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE player_inventory
+DROP COLUMN storage_level;
+
+DROP TABLE storage_tiers;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+CREATE TABLE storage_tiers (
+    level INTEGER NOT NULL PRIMARY KEY,
+    capacity INTEGER NOT NULL,
+    gold_cost INTEGER NOT NULL,
+    special_currency_cost INTEGER NOT NULL
+);
+
+INSERT INTO storage_tiers (level, capacity, gold_cost, special_currency_cost) VALUES
+    (1, 100, 0, 0),
+    (2, 200, 500, 0),
+    (3, 350, 1500, 5),
+    (4, 550, 4000, 15),
+    (5, 800, 10000, 40),
+    (6, 1100, 25000, 100);
+
+ALTER TABLE player_inventory ADD COLUMN storage_level INTEGER NOT NULL DEFAULT 1 REFERENCES storage_tiers(level);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+CREATE TABLE market_orders (
+    id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    side VARCHAR(4) NOT NULL CHECK (side IN ('buy', 'sell')),
+    good VARCHAR(30) NOT NULL,
+    price INTEGER NOT NULL CHECK (price > 0),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    remaining INTEGER NOT NULL CHECK (remaining >= 0 AND remaining <= quantity),
+    status VARCHAR(10) NOT NULL CHECK (status IN ('open', 'filled', 'cancelled')),
+    created_on TIMESTAMP NOT NULL
+);
+
+CREATE INDEX market_orders_book ON market_orders (good, side, price) WHERE status = 'open';
+CREATE INDEX market_orders_user_id ON market_orders (user_id, created_on DESC);
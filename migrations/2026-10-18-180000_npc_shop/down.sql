@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE shop_trades;
+DROP TABLE shop_goods;
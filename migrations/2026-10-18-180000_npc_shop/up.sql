@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+CREATE TABLE shop_goods (
+    good VARCHAR(30) NOT NULL PRIMARY KEY,
+    base_price INTEGER NOT NULL CHECK (base_price > 0),
+    liquidity INTEGER NOT NULL CHECK (liquidity > 0),
+    daily_cap INTEGER NOT NULL CHECK (daily_cap >= 0)
+);
+
+INSERT INTO shop_goods (good, base_price, liquidity, daily_cap) VALUES
+    ('food_q1', 5, 1000, 200),
+    ('weapon_q1', 12, 500, 100);
+
+CREATE TABLE shop_trades (
+    id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    good VARCHAR(30) NOT NULL REFERENCES shop_goods(good),
+    side VARCHAR(4) NOT NULL CHECK (side IN ('buy', 'sell')),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    price INTEGER NOT NULL,
+    created_on TIMESTAMP NOT NULL
+);
+
+CREATE INDEX shop_trades_good_created_on ON shop_trades (good, created_on);
+CREATE INDEX shop_trades_user_id_created_on ON shop_trades (user_id, created_on);
@@ -0,0 +1,35 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE shop_goods DROP CONSTRAINT shop_goods_good_fkey;
+ALTER TABLE market_orders DROP CONSTRAINT market_orders_good_fkey;
+
+ALTER TABLE factories ADD COLUMN product VARCHAR(50) NOT NULL DEFAULT 'food';
+
+UPDATE factories
+SET product = items.item_type
+FROM items
+WHERE items.id = factories.item_id;
+
+ALTER TABLE factories
+DROP COLUMN item_id;
+
+ALTER TABLE player_inventory
+ADD COLUMN food_q1 INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN weapon_q1 INTEGER NOT NULL DEFAULT 0;
+
+UPDATE player_inventory
+SET food_q1 = player_items.quantity
+FROM player_items, items
+WHERE player_items.player_inventory_id = player_inventory.id
+  AND player_items.item_id = items.id
+  AND items.code = 'food_q1';
+
+UPDATE player_inventory
+SET weapon_q1 = player_items.quantity
+FROM player_items, items
+WHERE player_items.player_inventory_id = player_inventory.id
+  AND player_items.item_id = items.id
+  AND items.code = 'weapon_q1';
+
+DROP TABLE player_items;
+DROP TABLE items;
@@ -0,0 +1,62 @@
+-- Your SQL goes here
+
+CREATE TABLE items (
+    id UUID NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    code VARCHAR(30) NOT NULL UNIQUE,
+    name VARCHAR(80) NOT NULL,
+    item_type VARCHAR(30) NOT NULL,
+    quality INTEGER NOT NULL CHECK (quality BETWEEN 1 AND 5),
+    stack_size INTEGER NOT NULL CHECK (stack_size > 0),
+    weight INTEGER NOT NULL CHECK (weight >= 0),
+    UNIQUE (item_type, quality)
+);
+
+INSERT INTO items (code, name, item_type, quality, stack_size, weight) VALUES
+    ('food_q1', 'Bread', 'food', 1, 100, 1),
+    ('food_q2', 'Cheese', 'food', 2, 100, 1),
+    ('food_q3', 'Stew', 'food', 3, 50, 1),
+    ('food_q4', 'Roast', 'food', 4, 50, 2),
+    ('food_q5', 'Feast', 'food', 5, 20, 2),
+    ('weapon_q1', 'Club', 'weapon', 1, 50, 1),
+    ('weapon_q2', 'Spear', 'weapon', 2, 50, 2),
+    ('weapon_q3', 'Sword', 'weapon', 3, 20, 2),
+    ('weapon_q4', 'Crossbow', 'weapon', 4, 20, 3),
+    ('weapon_q5', 'Cannon', 'weapon', 5, 5, 5);
+
+CREATE TABLE player_items (
+    id UUID NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    player_inventory_id UUID NOT NULL REFERENCES player_inventory(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES items(id),
+    quantity INTEGER NOT NULL CHECK (quantity >= 0),
+    UNIQUE (player_inventory_id, item_id)
+);
+
+INSERT INTO player_items (player_inventory_id, item_id, quantity)
+SELECT player_inventory.id, items.id, player_inventory.food_q1
+FROM player_inventory, items
+WHERE items.code = 'food_q1' AND player_inventory.food_q1 > 0;
+
+INSERT INTO player_items (player_inventory_id, item_id, quantity)
+SELECT player_inventory.id, items.id, player_inventory.weapon_q1
+FROM player_inventory, items
+WHERE items.code = 'weapon_q1' AND player_inventory.weapon_q1 > 0;
+
+ALTER TABLE player_inventory
+DROP COLUMN food_q1,
+DROP COLUMN weapon_q1;
+
+-- products were free text, anything that isn't a known type becomes food like the old default
+ALTER TABLE factories ADD COLUMN item_id UUID REFERENCES items(id);
+
+UPDATE factories
+SET item_id = COALESCE(
+    (SELECT id FROM items WHERE items.item_type = factories.product AND items.quality = 1),
+    (SELECT id FROM items WHERE items.code = 'food_q1')
+);
+
+ALTER TABLE factories
+ALTER COLUMN item_id SET NOT NULL,
+DROP COLUMN product;
+
+ALTER TABLE market_orders ADD FOREIGN KEY (good) REFERENCES items(code);
+ALTER TABLE shop_goods ADD FOREIGN KEY (good) REFERENCES items(code);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE crafting_jobs;
+DROP TABLE recipe_inputs;
+DROP TABLE recipes;
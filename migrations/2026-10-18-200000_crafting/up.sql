@@ -0,0 +1,48 @@
+-- Your SQL goes here
+
+CREATE TABLE recipes (
+    id UUID NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    code VARCHAR(30) NOT NULL UNIQUE,
+    name VARCHAR(80) NOT NULL,
+    output_item_id UUID NOT NULL REFERENCES items(id),
+    output_quantity INTEGER NOT NULL CHECK (output_quantity > 0),
+    energy_cost INTEGER NOT NULL CHECK (energy_cost >= 0),
+    gold_cost INTEGER NOT NULL CHECK (gold_cost >= 0),
+    duration_seconds INTEGER NOT NULL CHECK (duration_seconds >= 0)
+);
+
+CREATE TABLE recipe_inputs (
+    id UUID NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+    recipe_id UUID NOT NULL REFERENCES recipes(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES items(id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    UNIQUE (recipe_id, item_id)
+);
+
+-- three pieces of one tier make one of the next, food is cheaper and quicker than weapons
+INSERT INTO recipes (code, name, output_item_id, output_quantity, energy_cost, gold_cost, duration_seconds)
+SELECT code, name, id, 1,
+    CASE item_type WHEN 'food' THEN 5 ELSE 10 END,
+    (quality - 1) * CASE item_type WHEN 'food' THEN 10 ELSE 20 END,
+    (quality - 1) * CASE item_type WHEN 'food' THEN 60 ELSE 120 END
+FROM items
+WHERE quality > 1;
+
+INSERT INTO recipe_inputs (recipe_id, item_id, quantity)
+SELECT recipes.id, lower_tier.id, 3
+FROM recipes
+JOIN items output ON output.id = recipes.output_item_id
+JOIN items lower_tier ON lower_tier.item_type = output.item_type AND lower_tier.quality = output.quality - 1;
+
+-- inputs, gold and energy are spent when a job starts, the output is collected once ready_at passed
+CREATE TABLE crafting_jobs (
+    id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    recipe_id UUID NOT NULL REFERENCES recipes(id),
+    batches INTEGER NOT NULL CHECK (batches > 0),
+    started_at TIMESTAMP NOT NULL,
+    ready_at TIMESTAMP NOT NULL,
+    collected_at TIMESTAMP
+);
+
+CREATE INDEX crafting_jobs_user_id ON crafting_jobs (user_id, started_at DESC);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE trade_offer_items;
+DROP TABLE trade_offers;
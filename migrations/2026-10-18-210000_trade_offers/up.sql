@@ -0,0 +1,31 @@
+-- Your SQL goes here
+
+-- the sender's side is held in escrow while the offer is pending,
+-- the recipient's side only moves when they accept
+CREATE TABLE trade_offers (
+    id UUID NOT NULL PRIMARY KEY,
+    sender_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    recipient_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    offered_gold INTEGER NOT NULL CHECK (offered_gold >= 0),
+    offered_special_currency INTEGER NOT NULL CHECK (offered_special_currency >= 0),
+    requested_gold INTEGER NOT NULL CHECK (requested_gold >= 0),
+    requested_special_currency INTEGER NOT NULL CHECK (requested_special_currency >= 0),
+    status VARCHAR(10) NOT NULL
+        CHECK (status IN ('pending', 'accepted', 'rejected', 'cancelled', 'expired')),
+    created_on TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    resolved_at TIMESTAMP,
+    CHECK (sender_id <> recipient_id)
+);
+
+CREATE INDEX trade_offers_sender_id ON trade_offers (sender_id, created_on DESC);
+CREATE INDEX trade_offers_recipient_id ON trade_offers (recipient_id, created_on DESC);
+
+CREATE TABLE trade_offer_items (
+    id UUID NOT NULL PRIMARY KEY,
+    trade_offer_id UUID NOT NULL REFERENCES trade_offers(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES items(id),
+    side VARCHAR(10) NOT NULL CHECK (side IN ('offered', 'requested')),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    UNIQUE (trade_offer_id, side, item_id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+-- irreversible: the inflated gold_acc values were overwritten and can't be
+-- restored, fail instead of letting `diesel migration redo` pretend it undid them
+SELECT 1/0;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- working used to add gold_per_day to gold_acc on top of owning,
+-- reset it to what the owned factories actually pay
+UPDATE players_data
+SET gold_acc = COALESCE((
+    SELECT SUM(factories.gold_per_day * player_factories.amount)
+    FROM users
+    JOIN player_factories ON player_factories.user_id = users.id
+    JOIN factories ON factories.id = player_factories.factory_id
+    WHERE users.player_data_id = players_data.id
+), 0);
@@ -1,79 +1,156 @@
-// //auth_handler.rs
-// use actix_identity::Identity;
-// use actix_web::{
-//     dev::Payload, error::BlockingError, web, Error, FromRequest, HttpRequest, HttpResponse,
-// };
-// use diesel::prelude::*;
-// use futures::Future;
-
-// use crate::model::user::{NewUser, User};
-// use crate::share::db::Pool;
-
-// #[derive(Debug, Deserialize)]
-// pub struct AuthData {
-//     pub email: String,
-//     pub password: String,
-// }
-
-// // we need the same data
-// // simple aliasing makes the intentions clear and its more readable
-// pub type LoggedUser = NewUser;
-
-// // to get LoggedUser from auth cookie
-// impl FromRequest for LoggedUser {
-//     type Error = Error;
-//     type Future = Result<LoggedUser, Error>;
-//     type Config = ();
-
-//     fn from_request(req: &HttpRequest, pl: &mut Payload) -> Result<LoggedUser, Error> {
-//         if let Some(identity) = Identity::from_request(req, pl)?.identity() {
-//             let user: LoggedUser = serde_json::from_str(&identity)?;
-//             return Ok(user);
-//         }
-//         Err(Error::from(HttpResponse::Unauthorized().json("Un")))
-//     }
-// }
-
-// pub fn logout(id: Identity) -> HttpResponse {
-//     id.forget();
-//     HttpResponse::Ok().finish()
-// }
-
-// pub fn login(
-//     auth_data: web::Json<NewUser>,
-//     id: Identity,
-//     pool: web::Data<Pool>,
-// ) -> impl Future<Item = HttpResponse, Error> {
-//     web::block(move || query(auth_data.into_inner(), pool)).then(
-//         move |res: Result<NewUser, BlockingError>| match res {
-//             Ok(user) => {
-//                 let user_string = serde_json::to_string(&user).unwrap();
-//                 id.remember(user_string);
-//                 Ok(HttpResponse::Ok().finish())
-//             }
-//             Err(err) => match err {
-//                 BlockingError::Error(service_error) => Err(service_error),
-//                 BlockingError::Canceled => Err(ServiceError::InternalServerError),
-//             },
-//         },
-//     )
-// }
-
-// pub fn get_me(logged_user: LoggedUser) -> HttpResponse {
-//     HttpResponse::Ok().json(logged_user)
-// }
-// /// Diesel query
-// fn query(auth_data: AuthData, pool: web::Data<Pool>) -> Result<NewUser, Error> {
-//     use crate::schema::users::dsl::{email, users};
-//     let conn: &PgConnection = &pool.get().unwrap();
-//     let mut items = users
-//         .filter(email.eq(&auth_data.email))
-//         .load::<User>(conn)?;
-
-//     if let Some(user) = items.pop() {
-//         if let Ok(matching) {
-//             user.into()
-//         }
-//     }
-//     Err(ServiceError::Unauthorized)
-// }
+use actix_session::{Session, UserSession};
+use actix_web::{
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, HeaderMap},
+    web, Error, FromRequest, HttpRequest,
+};
+use diesel::prelude::*;
+use futures::future::{err, ok, FutureExt, LocalBoxFuture, Ready};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use crate::model::user::Role;
+use crate::share::{db::Pool, errors::ServiceError, jwt::decode_access_token};
+
+/// Player identity from a bearer access token or the session cookie set on login,
+/// game handlers take it instead of trusting an id from the request body
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggedUser {
+    pub id: uuid::Uuid,
+    pub role: Role,
+}
+
+/// (user id, session version) carried by the access token or the session cookie
+pub fn identity(headers: &HeaderMap, session: Session) -> Result<(uuid::Uuid, i32), ServiceError> {
+    if let Some(authorization) = headers.get(header::AUTHORIZATION) {
+        let claims = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ServiceError::Unauthorized)
+            .and_then(decode_access_token)?;
+
+        return Ok((claims.sub, claims.ver));
+    }
+
+    match (
+        session.get::<uuid::Uuid>("user_id"),
+        session.get::<i32>("session_version"),
+    ) {
+        (Ok(Some(id)), Ok(Some(version))) => Ok((id, version)),
+        _ => Err(ServiceError::Unauthorized),
+    }
+}
+
+fn query_session(user: uuid::Uuid, pool: web::Data<Pool>) -> Result<(i32, String), ServiceError> {
+    use crate::schema::users::dsl::{role, session_version, users};
+    let conn: &PgConnection = &*pool.get()?;
+
+    users
+        .find(user)
+        .select((session_version, role))
+        .first(conn)
+        .optional()?
+        .ok_or(ServiceError::Unauthorized)
+}
+
+impl FromRequest for LoggedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<LoggedUser, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
+        let identity = identity(req.headers(), req.get_session());
+        let pool = req.app_data::<web::Data<Pool>>().cloned();
+
+        async move {
+            let (id, version) = identity?;
+            let pool = pool.ok_or(ServiceError::InternalServerError)?;
+
+            // password reset bumps the version, older sessions and tokens stop working
+            let (current, role) = web::block(move || query_session(id, pool))
+                .await
+                .map_err(ServiceError::from)?;
+            if current != version {
+                return Err(ServiceError::Unauthorized.into());
+            }
+
+            Ok(LoggedUser {
+                id,
+                role: role.as_str().into(),
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// Middleware for resources that need at least `role`,
+/// e.g. `web::resource("/user/{id}").wrap(RequireRole(Role::Admin))`
+#[derive(Clone, Copy)]
+pub struct RequireRole(pub Role);
+
+impl<S, B> Transform<S> for RequireRole
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireRoleMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireRoleMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            role: self.0,
+        })
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    role: Role,
+}
+
+impl<S, B> Service for RequireRoleMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let role = self.role;
+
+        let (http_req, payload) = req.into_parts();
+        let user = LoggedUser::from_request(&http_req, &mut Payload::None);
+        let req = match ServiceRequest::from_parts(http_req, payload) {
+            Ok(req) => req,
+            Err(_) => return err(ServiceError::InternalServerError.into()).boxed_local(),
+        };
+
+        async move {
+            let user = user.await?;
+            if user.role < role {
+                return Err(ServiceError::Forbidden.into());
+            }
+
+            let response = service.borrow_mut().call(req);
+            response.await
+        }
+        .boxed_local()
+    }
+}
@@ -1,80 +1,81 @@
 use actix_web::{web, Error, HttpResponse};
 use diesel::prelude::*;
-use uuid;
 
+use crate::api::{
+    auth::LoggedUser,
+    ledger::record,
+    player::{add_item, item_by_code, item_count, load_player_data, lock_inventory},
+};
 use crate::model::{
-    player::{PlayerData, PlayerInventory},
-    user::User,
+    ledger::{LedgerEntry, Resource},
+    player::PlayerInventory,
 };
-use crate::share::db::Pool;
+use crate::share::{db::Pool, errors::ServiceError};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BattlePayload {
-    user_id: uuid::Uuid,
     battle_id: String,
 }
 
+// campaigns are fought with the basic weapon
+const BATTLE_WEAPON: &str = "weapon_q1";
+
 // campign 1  => - 10 eng -X products  + special_loot
 fn battle_query(
-    payload: web::Json<BattlePayload>,
+    user: LoggedUser,
+    _payload: web::Json<BattlePayload>,
     pool: web::Data<Pool>,
-) -> Result<String, diesel::result::Error> {
-    use crate::schema::player_inventory::dsl::{player_inventory, special_currency, weapon_q1};
+) -> Result<String, ServiceError> {
+    use crate::schema::player_inventory::dsl::{player_inventory, special_currency};
     use crate::schema::players_data::dsl::{energy, players_data};
-    use crate::schema::users::dsl::users;
-    let conn: &PgConnection = &pool.get().unwrap();
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let curr_player_data = load_player_data(conn, user.id)?;
+        let storage = lock_inventory(conn, &curr_player_data)?;
+        let weapon = item_by_code(conn, BATTLE_WEAPON)?;
+
+        // check if enough energy and resourses before anything is taken
+        if curr_player_data.energy < 10 {
+            return Err(ServiceError::BadRequest("Not enough energy".to_owned()));
+        }
+        if item_count(conn, &storage, &weapon)? < 10 {
+            return Err(ServiceError::BadRequest("Not enough weapons".to_owned()));
+        }
+
+        diesel::update(players_data.find(&curr_player_data.id))
+            .set(energy.eq(energy - 10))
+            .execute(conn)?;
+        // give new resourses
+        add_item(conn, user.id, &weapon, -10, "battle", "spent in battle")?;
+        let updated: PlayerInventory = diesel::update(player_inventory.find(&storage.id))
+            .set(special_currency.eq(special_currency + 5))
+            .get_result(conn)?;
 
-    let player: User = users.find(&payload.user_id).first(conn).unwrap();
-    let curr_player_data: PlayerData = players_data
-        .find(&player.player_data_id)
-        .first(conn)
-        .unwrap();
-    let storage: PlayerInventory = player_inventory
-        .filter(
-            player_inventory
-                .primary_key()
-                .eq(&curr_player_data.player_inventory_id),
-        )
-        .first(conn)
-        .unwrap();
+        record(
+            conn,
+            vec![LedgerEntry::new(
+                user.id,
+                Resource::SpecialCurrency,
+                5,
+                updated.special_currency,
+                "battle",
+                "battle loot",
+            )],
+        )?;
 
-    // check if enough energy
-    if curr_player_data.energy < 10 {
-        return Ok("Not enough energy".to_owned());
-    }
-    diesel::update(players_data)
-        .filter(players_data.primary_key().eq(&player.player_data_id))
-        .set(energy.eq(energy - 10))
-        .execute(conn)
-        .unwrap();
-    // check if enough resourses
-    if storage.weapon_q1 < 10 {
-        return Ok("Not enough weapons".to_owned());
-    }
-    // give new resourses
-    diesel::update(player_inventory)
-        .filter(
-            player_inventory
-                .primary_key()
-                .eq(&curr_player_data.player_inventory_id),
-        )
-        .set((
-            special_currency.eq(special_currency + 5),
-            weapon_q1.eq(weapon_q1 - 10),
-        ))
-        .execute(conn)
-        .expect("Updating player_iventory loot and wp1");
-    Ok(format!("Success"))
+        Ok("Success".to_owned())
+    })
 }
 
 /// work at specific company => - 10 energy + products
 pub async fn battle(
+    user: LoggedUser,
     player_data: web::Json<BattlePayload>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || battle_query(player_data, pool))
+    Ok(web::block(move || battle_query(user, player_data, pool))
         .await
         .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
+        .map_err(ServiceError::from)?)
 }
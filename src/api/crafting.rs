@@ -0,0 +1,318 @@
+use actix_web::{web, Error, HttpResponse};
+use chrono::prelude::*;
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{add_gold, add_item, free_capacity, item_count, load_player_data, lock_inventory},
+};
+use crate::model::{
+    crafting::{CraftOrder, CraftingJob, Recipe, RecipeInput},
+    item::Item,
+};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
+
+#[derive(Debug, Serialize)]
+pub struct ItemAmount {
+    pub item: String,
+    pub quantity: i32,
+}
+
+/// one recipe with item codes instead of ids, costs are per batch
+#[derive(Debug, Serialize)]
+pub struct RecipeView {
+    pub code: String,
+    pub name: String,
+    pub inputs: Vec<ItemAmount>,
+    pub output: ItemAmount,
+    pub energy_cost: i32,
+    pub gold_cost: i32,
+    pub duration_seconds: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobView {
+    pub id: uuid::Uuid,
+    pub recipe: String,
+    pub batches: i32,
+    pub output: ItemAmount,
+    pub started_at: NaiveDateTime,
+    pub ready_at: NaiveDateTime,
+    pub ready: bool,
+    pub collected_at: Option<NaiveDateTime>,
+}
+
+impl JobView {
+    fn new(job: CraftingJob, recipe: &Recipe, output: &Item) -> Self {
+        JobView {
+            id: job.id,
+            recipe: recipe.code.clone(),
+            batches: job.batches,
+            output: ItemAmount {
+                item: output.code.clone(),
+                quantity: recipe.output_quantity * job.batches,
+            },
+            started_at: job.started_at,
+            ready_at: job.ready_at,
+            ready: job.ready_at <= chrono::Utc::now().naive_utc(),
+            collected_at: job.collected_at,
+        }
+    }
+}
+
+fn inputs_of(
+    conn: &PgConnection,
+    recipe: &Recipe,
+) -> Result<Vec<(RecipeInput, Item)>, ServiceError> {
+    use crate::schema::items::dsl::{code, items};
+    use crate::schema::recipe_inputs::dsl::{recipe_id, recipe_inputs};
+
+    Ok(recipe_inputs
+        .inner_join(items)
+        .filter(recipe_id.eq(&recipe.id))
+        .order(code)
+        .load(conn)?)
+}
+
+fn recipe_of(conn: &PgConnection, job: &CraftingJob) -> Result<(Recipe, Item), ServiceError> {
+    use crate::schema::items::dsl::items;
+    use crate::schema::recipes::dsl::recipes;
+
+    recipes
+        .inner_join(items)
+        .filter(crate::schema::recipes::id.eq(&job.recipe_id))
+        .first(conn)
+        .or_not_found("Recipe")
+}
+
+fn query_recipes(pool: web::Data<Pool>) -> Result<Vec<RecipeView>, ServiceError> {
+    use crate::schema::items::dsl::items;
+    use crate::schema::recipe_inputs::dsl::recipe_inputs;
+    use crate::schema::recipes::dsl::{code, recipes};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let mut inputs: BTreeMap<uuid::Uuid, Vec<ItemAmount>> = BTreeMap::new();
+    for (input, item) in recipe_inputs
+        .inner_join(items)
+        .order(crate::schema::items::code)
+        .load::<(RecipeInput, Item)>(conn)?
+    {
+        inputs.entry(input.recipe_id).or_default().push(ItemAmount {
+            item: item.code,
+            quantity: input.quantity,
+        });
+    }
+
+    Ok(recipes
+        .inner_join(items)
+        .order(code)
+        .load::<(Recipe, Item)>(conn)?
+        .into_iter()
+        .map(|(recipe, output)| RecipeView {
+            inputs: inputs.remove(&recipe.id).unwrap_or_default(),
+            output: ItemAmount {
+                item: output.code,
+                quantity: recipe.output_quantity,
+            },
+            code: recipe.code,
+            name: recipe.name,
+            energy_cost: recipe.energy_cost,
+            gold_cost: recipe.gold_cost,
+            duration_seconds: recipe.duration_seconds,
+        })
+        .collect())
+}
+
+/// every recipe with its inputs, output and costs
+pub async fn get_recipes(pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_recipes(pool))
+        .await
+        .map(|listing| HttpResponse::Ok().json(listing))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_craft(
+    user: LoggedUser,
+    order: CraftOrder,
+    pool: web::Data<Pool>,
+) -> Result<JobView, ServiceError> {
+    use crate::schema::crafting_jobs::dsl::crafting_jobs;
+    use crate::schema::items::dsl::items;
+    use crate::schema::players_data::dsl::{energy, players_data};
+    use crate::schema::recipes::dsl::{code, recipes};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        let (recipe, output): (Recipe, Item) = recipes
+            .inner_join(items)
+            .filter(code.eq(&order.recipe))
+            .first(conn)
+            .or_not_found("Recipe")?;
+
+        // check everything before anything is taken
+        let energy_cost = recipe.energy_cost * order.batches;
+        if player_data.energy < energy_cost {
+            return Err(ServiceError::BadRequest("Not enough energy".to_owned()));
+        }
+        let gold_cost = i64::from(recipe.gold_cost) * i64::from(order.batches);
+        if i64::from(player_data.gold) < gold_cost {
+            return Err(ServiceError::BadRequest("Not enough gold".to_owned()));
+        }
+        let inputs = inputs_of(conn, &recipe)?;
+        let mut freed = 0;
+        for (input, item) in &inputs {
+            let needed = input.quantity * order.batches;
+            if item_count(conn, &inventory, item)? < needed {
+                return Err(ServiceError::BadRequest(format!(
+                    "Not enough {}",
+                    item.code
+                )));
+            }
+            freed += needed * item.weight;
+        }
+        let output_weight = recipe.output_quantity * order.batches * output.weight;
+        if free_capacity(conn, &inventory)? + freed < output_weight {
+            return Err(ServiceError::BadRequest(
+                "Not enough storage space".to_owned(),
+            ));
+        }
+
+        let reason = format!("{} × {}", order.batches, recipe.code);
+        diesel::update(players_data.find(&player_data.id))
+            .set(energy.eq(energy - energy_cost))
+            .execute(conn)?;
+        add_gold(conn, user.id, -(gold_cost as i32), "craft", &reason)?;
+        for (input, item) in &inputs {
+            add_item(
+                conn,
+                user.id,
+                item,
+                -input.quantity * order.batches,
+                "craft",
+                &reason,
+            )?;
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let duration = i64::from(recipe.duration_seconds) * i64::from(order.batches);
+        let job = CraftingJob {
+            id: uuid::Uuid::new_v4(),
+            user_id: user.id,
+            recipe_id: recipe.id,
+            batches: order.batches,
+            started_at: now,
+            ready_at: now + chrono::Duration::seconds(duration),
+            collected_at: None,
+        };
+        let job = diesel::insert_into(crafting_jobs)
+            .values(&job)
+            .get_result(conn)?;
+        Ok(JobView::new(job, &recipe, &output))
+    })
+}
+
+/// inputs, gold and energy => a crafting job that is ready after `duration_seconds` per batch
+pub async fn craft(
+    user: LoggedUser,
+    order: web::Json<CraftOrder>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let order = order.into_inner().validate()?;
+    Ok(web::block(move || query_craft(user, order, pool))
+        .await
+        .map(|job| HttpResponse::Ok().json(job))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_jobs(user: LoggedUser, pool: web::Data<Pool>) -> Result<Vec<JobView>, ServiceError> {
+    use crate::schema::crafting_jobs::dsl::{collected_at, crafting_jobs, ready_at, user_id};
+    use crate::schema::items::dsl::items;
+    use crate::schema::recipes::dsl::recipes;
+    let conn: &PgConnection = &*pool.get()?;
+
+    Ok(crafting_jobs
+        .inner_join(recipes.inner_join(items))
+        .filter(user_id.eq(&user.id))
+        .filter(collected_at.is_null())
+        .order(ready_at)
+        .load::<(CraftingJob, (Recipe, Item))>(conn)?
+        .into_iter()
+        .map(|(job, (recipe, output))| JobView::new(job, &recipe, &output))
+        .collect())
+}
+
+/// crafting jobs that haven't been collected, soonest first
+pub async fn get_jobs(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_jobs(user, pool))
+        .await
+        .map(|jobs| HttpResponse::Ok().json(jobs))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_collect_job(
+    user: LoggedUser,
+    job_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<JobView, ServiceError> {
+    use crate::schema::crafting_jobs::dsl::{collected_at, crafting_jobs, user_id};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        let job: CraftingJob = crafting_jobs
+            .find(&job_id)
+            .filter(user_id.eq(&user.id))
+            .for_update()
+            .first(conn)
+            .or_not_found("Crafting job")?;
+        if job.collected_at.is_some() {
+            return Err(ServiceError::BadRequest("Already collected".to_owned()));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        if job.ready_at > now {
+            return Err(ServiceError::BadRequest("Not ready yet".to_owned()));
+        }
+
+        // the job stays ready until there is room for the whole output
+        let (recipe, output) = recipe_of(conn, &job)?;
+        let quantity = recipe.output_quantity * job.batches;
+        if free_capacity(conn, &inventory)? < quantity * output.weight {
+            return Err(ServiceError::BadRequest(
+                "Not enough storage space".to_owned(),
+            ));
+        }
+        add_item(
+            conn,
+            user.id,
+            &output,
+            quantity,
+            "craft_collect",
+            &format!("crafting job {}", job.id),
+        )?;
+        let job = diesel::update(crafting_jobs.find(&job.id))
+            .set(collected_at.eq(Some(now)))
+            .get_result(conn)?;
+        Ok(JobView::new(job, &recipe, &output))
+    })
+}
+
+/// finished crafting job => its output in the inventory
+pub async fn collect_job(
+    user: LoggedUser,
+    job_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_collect_job(user, job_id.into_inner(), pool))
+            .await
+            .map(|job| HttpResponse::Ok().json(job))
+            .map_err(ServiceError::from)?,
+    )
+}
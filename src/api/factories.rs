@@ -2,18 +2,28 @@ use actix_web::{web, Error, HttpResponse};
 use diesel::prelude::*;
 use uuid;
 
+use crate::api::{
+    auth::LoggedUser,
+    ledger::record,
+    player::{add_item, free_capacity, load_player_data, lock_inventory},
+    production::collect_production,
+};
 use crate::model::{
     factory::Factory,
+    item::Item,
+    ledger::{LedgerEntry, Resource},
     player::{PlayerData, PlayerFactories, PlayerInventory},
-    user::User,
 };
-use crate::share::db::Pool;
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
 
-fn query_get_factories(pool: web::Data<Pool>) -> Result<Vec<Factory>, diesel::result::Error> {
+fn query_get_factories(pool: web::Data<Pool>) -> Result<Vec<Factory>, ServiceError> {
     use crate::schema::factories::dsl::*;
-    let conn: &PgConnection = &pool.get().unwrap();
+    let conn: &PgConnection = &*pool.get()?;
 
-    let items = factories.load::<Factory>(conn).unwrap();
+    let items = factories.load::<Factory>(conn)?;
     Ok(items)
 }
 
@@ -21,343 +31,503 @@ pub async fn get_factories(pool: web::Data<Pool>) -> Result<HttpResponse, Error>
     Ok(web::block(move || query_get_factories(pool))
         .await
         .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
-}
-
-fn query_get_player_factories(
-    user: web::Json<UserId>,
-    pool: web::Data<Pool>,
-) -> Result<Vec<PlayerFactories>, diesel::result::Error> {
-    use crate::schema::player_factories::dsl::*;
-    let conn: &PgConnection = &pool.get().unwrap();
-
-    let items = player_factories
-        .filter(user_id.eq(&user.id))
-        .load::<PlayerFactories>(conn)?;
-
-    Ok(items)
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct UserId {
-    pub id: uuid::Uuid,
+        .map_err(ServiceError::from)?)
 }
 
-pub async fn get_player_factories(
-    user: web::Json<UserId>,
-    pool: web::Data<Pool>,
-) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || query_get_player_factories(user, pool))
-        .await
-        .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
-}
+/// special_currency every factory upgrade costs on top of the factory price
+const UPGRADE_SPECIAL_CURRENCY: i32 = 10;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerPayload {
-    pub user_id: uuid::Uuid,
     pub factory_id: uuid::Uuid,
 }
 
+/// ownership row after a purchase together with the new balance
+#[derive(Debug, Serialize)]
+pub struct Purchase {
+    pub player_factory: PlayerFactories,
+    pub price: i32,
+    pub next_price: i32,
+    pub gold: i32,
+    pub gold_acc: i32,
+}
+
 fn query_add_player_factories(
+    user: LoggedUser,
     payload: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
-) -> Result<PlayerFactories, diesel::result::Error> {
-    use crate::schema::factories::dsl::{factories, gold_per_day, id};
+) -> Result<Purchase, ServiceError> {
+    use crate::schema::factories::dsl::factories;
     use crate::schema::player_factories::dsl::{amount, factory_id, player_factories, user_id};
-    use crate::schema::players_data::dsl::{gold_acc, players_data};
-    let conn: &PgConnection = &pool.get().unwrap();
-
-    let item = player_factories
-        .filter(user_id.eq(&payload.user_id))
-        .filter(factory_id.eq(&payload.factory_id))
-        .get_result::<PlayerFactories>(conn)
-        .optional()?;
-
-    let new_factories = match item {
-        Some(data) => {
-            let new_amount = data.amount + 1;
-
-            let updated = diesel::update(player_factories)
-                .filter(user_id.eq(&payload.user_id))
-                .filter(factory_id.eq(&payload.factory_id))
-                .set(amount.eq(new_amount))
-                .get_result(conn)?;
-
-            Ok(updated)
-        }
-        None => {
-            let new_factories = PlayerFactories {
-                id: uuid::Uuid::new_v4(),
-                user_id: payload.user_id,
-                factory_id: payload.factory_id,
-                amount: 1,
-            };
-
-            diesel::insert_into(player_factories)
-                .values(&new_factories)
-                .execute(conn)?;
-
-            Ok(new_factories)
+    use crate::schema::players_data::dsl::{gold, gold_acc, players_data};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        // settle idle gold and production at the old rates before ownership changes,
+        // this also locks the player so parallel actions queue up
+        let curr_player_data = load_player_data(conn, user.id)?;
+        collect_production(conn, user.id, &curr_player_data)?;
+
+        let factory: Factory = factories
+            .find(&payload.factory_id)
+            .first(conn)
+            .or_not_found("Factory")?;
+
+        let item = player_factories
+            .filter(user_id.eq(&user.id))
+            .filter(factory_id.eq(&factory.id))
+            .for_update()
+            .get_result::<PlayerFactories>(conn)
+            .optional()?;
+
+        let owned = item.as_ref().map_or(0, |data| data.amount);
+        let price = factory.price_for(owned);
+        if curr_player_data.gold < price {
+            return Err(ServiceError::BadRequest(format!(
+                "Not enough gold: price {}, you have {}",
+                price, curr_player_data.gold
+            )));
         }
-    };
 
-    let gold = factories
-        .filter(id.eq(&payload.factory_id))
-        .select(gold_per_day)
-        .first::<i32>(conn)
-        .unwrap();
-
-    diesel::update(players_data)
-        .set(gold_acc.eq(gold_acc + gold))
-        .execute(conn)
-        .unwrap();
-
-    new_factories
+        let player_factory: PlayerFactories = match item {
+            Some(data) => diesel::update(player_factories.find(&data.id))
+                .set(amount.eq(amount + 1))
+                .get_result(conn)?,
+            None => {
+                let new_factories = PlayerFactories {
+                    id: uuid::Uuid::new_v4(),
+                    user_id: user.id,
+                    factory_id: factory.id,
+                    amount: 1,
+                    produced_at: chrono::Utc::now().naive_utc(),
+                };
+
+                diesel::insert_into(player_factories)
+                    .values(&new_factories)
+                    .get_result(conn)?
+            }
+        };
+
+        let updated: PlayerData = diesel::update(players_data.find(&curr_player_data.id))
+            .set((
+                gold.eq(gold - price),
+                gold_acc.eq(gold_acc + factory.gold_per_day),
+            ))
+            .get_result(conn)?;
+
+        let reason = format!("bought {}", factory.name);
+        record(
+            conn,
+            vec![
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Gold,
+                    -price,
+                    updated.gold,
+                    "buy_factory",
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Factory(factory.id),
+                    1,
+                    player_factory.amount,
+                    "buy_factory",
+                    &reason,
+                ),
+            ],
+        )?;
+
+        Ok(Purchase {
+            next_price: factory.price_for(player_factory.amount),
+            player_factory,
+            price,
+            gold: updated.gold,
+            gold_acc: updated.gold_acc,
+        })
+    })
 }
 
 pub async fn add_player_factories(
+    user: LoggedUser,
     player_data: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, Error> {
     Ok(
-        web::block(move || query_add_player_factories(player_data, pool))
+        web::block(move || query_add_player_factories(user, player_data, pool))
             .await
             .map(|user| HttpResponse::Ok().json(user))
-            .map_err(|_| HttpResponse::InternalServerError())
-            .expect("General buying new factory"),
+            .map_err(ServiceError::from)?,
     )
 }
 /// diesel::work at specific company => - 10 energy + products
 fn work_query(
+    user: LoggedUser,
     payload: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
-) -> Result<String, diesel::result::Error> {
+) -> Result<String, ServiceError> {
     use crate::schema::factories::dsl::factories;
-    // use crate::schema::player_factories::dsl::{amount, factory_id, player_factories, user_id};
-    use crate::schema::player_inventory::dsl::{food_q1, player_inventory, weapon_q1};
-    use crate::schema::players_data::dsl::{energy, gold_acc, players_data};
-    use crate::schema::users::dsl::users;
-    let conn: &PgConnection = &pool.get().unwrap();
-
-    let current_factory = factories
-        .filter(factories.primary_key().eq(&payload.factory_id))
-        .first::<Factory>(conn)
-        .unwrap();
-
-    let player: User = users.find(&payload.user_id).first(conn).unwrap();
-    let curr_player_data: PlayerData = players_data
-        .find(&player.player_data_id)
-        .first(conn)
-        .unwrap();
-
-    let storage: PlayerInventory = player_inventory
-        .filter(
-            player_inventory
-                .primary_key()
-                .eq(&curr_player_data.player_inventory_id),
-        )
-        .first(conn)
-        .unwrap();
-
-    // ?. check if has storage space
-    let current_storage = storage.food_q1 + storage.weapon_q1;
-    if storage.capacity < current_storage + current_factory.product_amount {
-        return Ok(format!(
-            "Cappacity Reached {}: current:{}, new:{}",
-            storage.capacity, current_storage, current_factory.product_amount
-        ));
-    }
-
-    // 1. Take from player_data -10 energy
-    diesel::update(players_data)
-        .filter(players_data.primary_key().eq(&player.player_data_id))
-        .set(energy.eq(energy - 10))
-        .execute(conn)
-        .unwrap();
-    // 2. add specific factory product to player inventory
-    if current_factory.product == "food".to_owned() {
-        println!("TESTING FOOD");
-        diesel::update(player_inventory)
-            .filter(
-                player_inventory
-                    .primary_key()
-                    .eq(&curr_player_data.player_inventory_id),
-            )
-            .set(food_q1.eq(food_q1 + current_factory.product_amount))
-            .execute(conn)
-            .unwrap();
-    }
-    if current_factory.product == "weapon".to_owned() {
-        diesel::update(player_inventory)
-            .filter(
-                player_inventory
-                    .primary_key()
-                    .eq(&curr_player_data.player_inventory_id),
-            )
-            .set(weapon_q1.eq(weapon_q1 + current_factory.product_amount))
-            .execute(conn)
-            .unwrap();
-    }
-    // ?check if he owns that company
-    // let item = player_factories
-    //     .filter(user_id.eq(&payload.user_id))
-    //     .filter(factory_id.eq(&payload.factory_id))
-    //     .get_result::<PlayerFactories>(conn)
-    //     .optional()?;
-
-    diesel::update(players_data)
-        .filter(players_data.primary_key().eq(&player.player_data_id))
-        .set(gold_acc.eq(gold_acc + current_factory.gold_per_day))
-        .execute(conn)
-        .unwrap();
-
-    //new_factories
-    Ok(format!(
-        "Success, u earned {} {}",
-        current_factory.product_amount, current_factory.product
-    ))
+    use crate::schema::items::dsl::items;
+    use crate::schema::player_factories::dsl::{factory_id, player_factories, user_id};
+    use crate::schema::players_data::dsl::{energy, players_data};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let curr_player_data = load_player_data(conn, user.id)?;
+
+        let current_factory = factories
+            .find(&payload.factory_id)
+            .first::<Factory>(conn)
+            .or_not_found("Factory")?;
+
+        // check if he owns that company
+        let owns: bool = diesel::select(diesel::dsl::exists(
+            player_factories
+                .filter(user_id.eq(&user.id))
+                .filter(factory_id.eq(&current_factory.id)),
+        ))
+        .get_result(conn)?;
+        if !owns {
+            return Err(ServiceError::BadRequest(
+                "You don't own this factory".to_owned(),
+            ));
+        }
+
+        if curr_player_data.energy < 10 {
+            return Err(ServiceError::BadRequest("Not enough energy".to_owned()));
+        }
+
+        // check if has storage space
+        let storage = lock_inventory(conn, &curr_player_data)?;
+        let product = items
+            .find(&current_factory.item_id)
+            .first::<Item>(conn)
+            .or_not_found("Item")?;
+        let needed = product.weight * current_factory.product_amount;
+        let free = free_capacity(conn, &storage)?;
+        if free < needed {
+            return Err(ServiceError::BadRequest(format!(
+                "Cappacity Reached {}: free:{}, new:{}",
+                storage.capacity, free, needed
+            )));
+        }
+
+        // 1. Take from player_data -10 energy, idle gold comes from owning, not working
+        diesel::update(players_data.find(&curr_player_data.id))
+            .set(energy.eq(energy - 10))
+            .execute(conn)?;
+        // 2. add specific factory product to player inventory
+        let reason = format!("worked at {}", current_factory.name);
+        add_item(
+            conn,
+            user.id,
+            &product,
+            current_factory.product_amount,
+            "work",
+            &reason,
+        )?;
+
+        Ok(format!(
+            "Success, u earned {} {}",
+            current_factory.product_amount, product.name
+        ))
+    })
 }
 
 /// work at specific company => - 10 energy + products
 pub async fn work_factory(
+    user: LoggedUser,
     player_data: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || work_query(player_data, pool))
+    Ok(web::block(move || work_query(user, player_data, pool))
         .await
         .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
+        .map_err(ServiceError::from)?)
+}
+
+/// what happens to the gold when a factory goes away
+#[derive(Clone, Copy, Debug)]
+enum Removal {
+    Sell,
+    Demolish,
+}
+
+/// ownership row left after selling (null when the last one went) and the new balance
+#[derive(Debug, Serialize)]
+pub struct Sale {
+    pub player_factory: Option<PlayerFactories>,
+    pub refund: i32,
+    pub gold: i32,
+    pub gold_acc: i32,
+}
+
+fn remove_factory_query(
+    user: LoggedUser,
+    payload: web::Json<PlayerPayload>,
+    removal: Removal,
+    pool: web::Data<Pool>,
+) -> Result<Sale, ServiceError> {
+    use crate::schema::factories::dsl::factories;
+    use crate::schema::player_factories::dsl::{amount, factory_id, player_factories, user_id};
+    use crate::schema::players_data::dsl::{gold, gold_acc, players_data};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        // settle idle gold and production at the old rates before ownership changes
+        let curr_player_data = load_player_data(conn, user.id)?;
+        collect_production(conn, user.id, &curr_player_data)?;
+
+        let factory: Factory = factories
+            .find(&payload.factory_id)
+            .first(conn)
+            .or_not_found("Factory")?;
+
+        let owned = player_factories
+            .filter(user_id.eq(&user.id))
+            .filter(factory_id.eq(&factory.id))
+            .for_update()
+            .get_result::<PlayerFactories>(conn)
+            .optional()?
+            .ok_or_else(|| ServiceError::BadRequest("You don't own this factory".to_owned()))?;
+
+        let (refund, action) = match removal {
+            Removal::Sell => (factory.refund(), "sell_factory"),
+            Removal::Demolish => (0, "demolish_factory"),
+        };
+
+        let player_factory: Option<PlayerFactories> = match owned.amount > 1 {
+            true => Some(
+                diesel::update(player_factories.find(&owned.id))
+                    .set(amount.eq(amount - 1))
+                    .get_result(conn)?,
+            ),
+            false => {
+                diesel::delete(player_factories.find(&owned.id)).execute(conn)?;
+                None
+            }
+        };
+
+        let updated: PlayerData = diesel::update(players_data.find(&curr_player_data.id))
+            .set((
+                gold.eq(gold + refund),
+                gold_acc.eq(gold_acc - factory.gold_per_day),
+            ))
+            .get_result(conn)?;
+
+        let reason = match removal {
+            Removal::Sell => format!("sold {}", factory.name),
+            Removal::Demolish => format!("demolished {}", factory.name),
+        };
+        record(
+            conn,
+            vec![
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Gold,
+                    refund,
+                    updated.gold,
+                    action,
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Factory(factory.id),
+                    -1,
+                    owned.amount - 1,
+                    action,
+                    &reason,
+                ),
+            ],
+        )?;
+
+        Ok(Sale {
+            player_factory,
+            refund,
+            gold: updated.gold,
+            gold_acc: updated.gold_acc,
+        })
+    })
+}
+
+/// sell one owned factory => + part of its price back, - its gold per day
+pub async fn sell_factory(
+    user: LoggedUser,
+    player_data: web::Json<PlayerPayload>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || remove_factory_query(user, player_data, Removal::Sell, pool))
+            .await
+            .map(|sale| HttpResponse::Ok().json(sale))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+/// tear down one owned factory without a refund
+pub async fn demolish_factory(
+    user: LoggedUser,
+    player_data: web::Json<PlayerPayload>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || remove_factory_query(user, player_data, Removal::Demolish, pool))
+            .await
+            .map(|sale| HttpResponse::Ok().json(sale))
+            .map_err(ServiceError::from)?,
+    )
 }
 
 /// delete old company, - resourses, + new company
 fn upgrade_factory_query(
+    user: LoggedUser,
     payload: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
-) -> Result<String, diesel::result::Error> {
-    use crate::schema::factories::dsl::{factories, level, product};
+) -> Result<String, ServiceError> {
+    use crate::schema::factories::dsl::{factories, item_id, level};
     use crate::schema::player_factories::dsl::{amount, factory_id, player_factories, user_id};
     use crate::schema::player_inventory::dsl::{player_inventory, special_currency};
     use crate::schema::players_data::dsl::{gold, gold_acc, players_data};
-    use crate::schema::users::dsl::users;
-    let conn: &PgConnection = &pool.get().unwrap();
-
-    let player: User = users
-        .find(&payload.user_id)
-        .first(conn)
-        .expect("Cant Find Player");
-    let curr_player_data: PlayerData = players_data
-        .find(&player.player_data_id)
-        .first(conn)
-        .expect("No player data");
-
-    let current_factory: Factory = factories
-        .filter(factories.primary_key().eq(&payload.factory_id))
-        .first::<Factory>(conn)
-        .expect("Cant Find Factory");
-
-    //  dbg!(&player);
-    let inventory: PlayerInventory = player_inventory
-        .filter(
-            player_inventory
-                .primary_key()
-                .eq(&curr_player_data.player_inventory_id),
-        )
-        .first(conn)
-        .expect("Cant Find Inventory");
-
-    //1. check if you have enough gold and resourses
-    // check if has storage space
-    // //! ADD SPECIAL CURRECNCY PRICE !!
-    if curr_player_data.gold < current_factory.price || inventory.special_currency < 10 {
-        return Ok(format!("You don't have enough resourses"));
-    }
-    //2. delete old company
-    let item = player_factories
-        .filter(user_id.eq(&payload.user_id))
-        .filter(factory_id.eq(&payload.factory_id))
-        .get_result::<PlayerFactories>(conn)
-        .optional()?;
-
-    let _item_test = match item {
-        Some(factory) => {
-            if factory.amount > 1 {
-                diesel::update(player_factories)
-                    .filter(user_id.eq(&payload.user_id))
-                    .filter(factory_id.eq(&payload.factory_id))
-                    .set(amount.eq(amount - 1))
-                    .execute(conn)
-                    .expect("Updating player_factories amount");
-            } else {
-                diesel::delete(
-                    player_factories
-                        .filter(user_id.eq(&payload.user_id))
-                        .filter(factory_id.eq(&payload.factory_id)),
-                )
-                .execute(conn)
-                .expect("Deleting old Factory");
-            }
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let curr_player_data = load_player_data(conn, user.id)?;
+        collect_production(conn, user.id, &curr_player_data)?;
+
+        let current_factory: Factory = factories
+            .find(&payload.factory_id)
+            .first(conn)
+            .or_not_found("Factory")?;
+
+        let owned = player_factories
+            .filter(user_id.eq(&user.id))
+            .filter(factory_id.eq(&current_factory.id))
+            .for_update()
+            .get_result::<PlayerFactories>(conn)
+            .optional()?
+            .ok_or_else(|| ServiceError::BadRequest("You don't own this factory".to_owned()))?;
+
+        //1. check if you have enough gold and resourses
+        let inventory = lock_inventory(conn, &curr_player_data)?;
+        if curr_player_data.gold < current_factory.price
+            || inventory.special_currency < UPGRADE_SPECIAL_CURRENCY
+        {
+            return Err(ServiceError::BadRequest(
+                "You don't have enough resourses".to_owned(),
+            ));
         }
-        None => return Ok(format!("You don't own this factory")),
-    };
-
-    //3. remove resourses
-    diesel::update(players_data)
-        .filter(players_data.primary_key().eq(&player.player_data_id))
-        .set(gold.eq(gold - current_factory.price))
-        .execute(conn)
-        .expect("Error updating player_data gold");
-
-    diesel::update(player_inventory)
-        .filter(
-            player_inventory
-                .primary_key()
-                .eq(&curr_player_data.player_inventory_id),
-        )
-        .set(special_currency.eq(special_currency - 0))
-        .execute(conn)
-        .expect("Can't update special currency");
-    //4. add new company
-    let new_factory: Factory = factories
-        .filter(product.eq(current_factory.product))
-        .filter(level.eq(current_factory.level + 1))
-        .first(conn)
-        .expect("No factory to upgrade");
-
-    let new_factories = PlayerFactories {
-        id: uuid::Uuid::new_v4(),
-        user_id: payload.user_id,
-        factory_id: new_factory.id,
-        amount: 1,
-    };
-
-    diesel::insert_into(player_factories)
-        .values(&new_factories)
-        .execute(conn)?;
-
-    diesel::update(players_data)
-        .set(gold_acc.eq(gold_acc - current_factory.gold_per_day + new_factory.gold_per_day))
-        .execute(conn)
-        .expect("Updating player_data Err");
-
-    //new_factories
-    Ok(format!(
-        "Successfully upgraded to level {}",
-        new_factory.level
-    ))
+
+        let new_factory: Factory = factories
+            .filter(item_id.eq(&current_factory.item_id))
+            .filter(level.eq(current_factory.level + 1))
+            .first(conn)
+            .or_not_found("Next level factory")?;
+
+        //2. delete old company
+        if owned.amount > 1 {
+            diesel::update(player_factories.find(&owned.id))
+                .set(amount.eq(amount - 1))
+                .execute(conn)?;
+        } else {
+            diesel::delete(player_factories.find(&owned.id)).execute(conn)?;
+        }
+
+        //3. remove resourses
+        let updated: PlayerData = diesel::update(players_data.find(&curr_player_data.id))
+            .set((
+                gold.eq(gold - current_factory.price),
+                gold_acc.eq(gold_acc - current_factory.gold_per_day + new_factory.gold_per_day),
+            ))
+            .get_result(conn)?;
+
+        let updated_inventory: PlayerInventory =
+            diesel::update(player_inventory.find(&inventory.id))
+                .set(special_currency.eq(special_currency - UPGRADE_SPECIAL_CURRENCY))
+                .get_result(conn)?;
+
+        //4. add new company
+        let next_owned = player_factories
+            .filter(user_id.eq(&user.id))
+            .filter(factory_id.eq(&new_factory.id))
+            .for_update()
+            .get_result::<PlayerFactories>(conn)
+            .optional()?;
+
+        let upgraded: PlayerFactories = match next_owned {
+            Some(data) => diesel::update(player_factories.find(&data.id))
+                .set(amount.eq(amount + 1))
+                .get_result(conn)?,
+            None => {
+                let new_factories = PlayerFactories {
+                    id: uuid::Uuid::new_v4(),
+                    user_id: user.id,
+                    factory_id: new_factory.id,
+                    amount: 1,
+                    produced_at: chrono::Utc::now().naive_utc(),
+                };
+
+                diesel::insert_into(player_factories)
+                    .values(&new_factories)
+                    .get_result(conn)?
+            }
+        };
+
+        let reason = format!("upgraded {} to {}", current_factory.name, new_factory.name);
+        record(
+            conn,
+            vec![
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Gold,
+                    -current_factory.price,
+                    updated.gold,
+                    "upgrade_factory",
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::SpecialCurrency,
+                    -UPGRADE_SPECIAL_CURRENCY,
+                    updated_inventory.special_currency,
+                    "upgrade_factory",
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Factory(current_factory.id),
+                    -1,
+                    owned.amount - 1,
+                    "upgrade_factory",
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Factory(new_factory.id),
+                    1,
+                    upgraded.amount,
+                    "upgrade_factory",
+                    &reason,
+                ),
+            ],
+        )?;
+
+        Ok(format!(
+            "Successfully upgraded to level {}",
+            new_factory.level
+        ))
+    })
 }
 
 /// upgrade company => - resourses + add new factory remove old
 pub async fn upgrade_factory(
+    user: LoggedUser,
     player_data: web::Json<PlayerPayload>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || upgrade_factory_query(player_data, pool))
-        .await
-        .map(|result| HttpResponse::Ok().json(result))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .expect("General upgrade factory Error"))
+    Ok(
+        web::block(move || upgrade_factory_query(user, player_data, pool))
+            .await
+            .map(|result| HttpResponse::Ok().json(result))
+            .map_err(ServiceError::from)?,
+    )
 }
@@ -1,47 +1,64 @@
-// use actix_web::{error::BlockingError, web, HttpResponse};
-// use diesel::{prelude::*, PgConnection};
-// use futures::Future;
-
-// // other stuff
-// use crate::model::invitations;
-// use crate::share::{db::Pool, errors::ServiceError};
-
-// // struct to hold user sent data
-// #[derive(Deserialize)]
-// pub struct InvitationData {
-//     pub email: String,
-// }
-
-// pub fn post_invitation(
-//     invitation_data: web::Json<InvitationData>,
-//     pool: web::Data<Pool>,
-// ) -> impl Future<Item = HttpResponse, Error = ServiceError> {
-//     web::block(move || create_invitation(signup_invitation.into_inner().email, pool)).then(|res| {
-//         match res {
-//             Ok(_) => Ok(HttpResponse::Ok().finish()),
-//             Err(err) => match err {
-//                 BlockingError::Error(service_error) => Err(service_error),
-//                 BlockingError::Canceled => Err(ServiceError::InternalServerError),
-//             },
-//         }
-//     })
-// }
-
-// fn create_invitation(eml: String, pool: web::Data<Pool>) -> Result<(), ServiceError> {
-//     let invitation = dbg!(query(eml, pool)?);
-//     // send_invitation(&invitation)
-// }
-
-// ///Diesel Query
-// fn query(eml: String, pool: web::Data<Pool>) -> Result<Invitation, ServiceError> {
-//     use crate::schema::invitations::dsl::invitations;
-
-//     let new_invitation: Invitation = eml.into();
-//     let conn: &PgConnection = &pool.get().unwrap();
-
-//     let inserted_invitation = diesel::insert_into(invitations)
-//         .values(&new_invitation)
-//         .get_result(conn)?;
-
-//     Ok(inserted_invitation)
-// }
+use actix_web::{web, Error, HttpResponse};
+use diesel::{prelude::*, PgConnection};
+use std::sync::Arc;
+
+use crate::model::invitations::Invitation;
+use crate::share::{
+    db::Pool,
+    email::{invitation_email, Mailer},
+    errors::ServiceError,
+    validation::{is_valid_email, normalize_email, ValidationErrors, EMAIL_MAX},
+};
+
+// struct to hold user sent data
+#[derive(Deserialize)]
+pub struct InvitationData {
+    pub email: String,
+}
+
+pub async fn post_invitation(
+    invitation_data: web::Json<InvitationData>,
+    pool: web::Data<Pool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+) -> Result<HttpResponse, Error> {
+    let eml = normalize_email(&invitation_data.into_inner().email);
+    let mut errors = ValidationErrors::default();
+    if eml.chars().count() > EMAIL_MAX {
+        errors.add("email", "must be at most 80 characters");
+    }
+    if !is_valid_email(&eml) {
+        errors.add("email", "is not a valid email address");
+    }
+    if !errors.is_empty() {
+        return Err(ServiceError::Validation(errors).into());
+    }
+
+    web::block(move || create_invitation(eml, pool, mailer))
+        .await
+        .map_err(ServiceError::from)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn create_invitation(
+    eml: String,
+    pool: web::Data<Pool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+) -> Result<(), ServiceError> {
+    let invitation = query(eml, pool)?;
+    mailer.send(&invitation_email(&invitation))
+}
+
+///Diesel Query
+fn query(eml: String, pool: web::Data<Pool>) -> Result<Invitation, ServiceError> {
+    use crate::schema::invitations::dsl::invitations;
+
+    let new_invitation: Invitation = eml.into();
+    let conn: &PgConnection = &*pool.get()?;
+
+    let inserted_invitation = diesel::insert_into(invitations)
+        .values(&new_invitation)
+        .get_result(conn)?;
+
+    Ok(inserted_invitation)
+}
@@ -0,0 +1,20 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::prelude::*;
+
+use crate::model::item::Item;
+use crate::share::{db::Pool, errors::ServiceError};
+
+fn query_get_items(pool: web::Data<Pool>) -> Result<Vec<Item>, ServiceError> {
+    use crate::schema::items::dsl::{code, items};
+    let conn: &PgConnection = &*pool.get()?;
+
+    Ok(items.order(code).load::<Item>(conn)?)
+}
+
+/// the item catalog
+pub async fn get_items(pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_get_items(pool))
+        .await
+        .map(|catalog| HttpResponse::Ok().json(catalog))
+        .map_err(ServiceError::from)?)
+}
@@ -0,0 +1,78 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::prelude::*;
+
+use crate::api::auth::LoggedUser;
+use crate::model::ledger::LedgerEntry;
+use crate::share::{
+    db::Pool,
+    errors::ServiceError,
+    pagination::{Page, Pagination},
+};
+
+/// append entries inside the caller's transaction, zero changes are skipped
+pub fn record(conn: &PgConnection, entries: Vec<LedgerEntry>) -> Result<(), ServiceError> {
+    use crate::schema::ledger_entries::dsl::ledger_entries;
+
+    let entries: Vec<LedgerEntry> = entries.into_iter().filter(|e| e.delta != 0).collect();
+    if !entries.is_empty() {
+        diesel::insert_into(ledger_entries)
+            .values(&entries)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn query_ledger(
+    player_id: uuid::Uuid,
+    pagination: Pagination,
+    pool: web::Data<Pool>,
+) -> Result<Page<LedgerEntry>, ServiceError> {
+    use crate::schema::ledger_entries::dsl::{created_on, id, ledger_entries, user_id};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let total: i64 = ledger_entries
+        .filter(user_id.eq(&player_id))
+        .count()
+        .get_result(conn)?;
+    let items = ledger_entries
+        .filter(user_id.eq(&player_id))
+        .order((created_on.desc(), id))
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .load::<LedgerEntry>(conn)?;
+
+    Ok(Page {
+        items,
+        page: pagination.page(),
+        per_page: pagination.per_page(),
+        total,
+    })
+}
+
+/// newest first history of the logged in player
+pub async fn get_ledger(
+    user: LoggedUser,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_ledger(user.id, pagination.into_inner(), pool))
+            .await
+            .map(|page| HttpResponse::Ok().json(page))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+///ADMIN any player's history, also after the account is gone
+pub async fn get_user_ledger(
+    player_id: web::Path<uuid::Uuid>,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_ledger(player_id.into_inner(), pagination.into_inner(), pool))
+            .await
+            .map(|page| HttpResponse::Ok().json(page))
+            .map_err(ServiceError::from)?,
+    )
+}
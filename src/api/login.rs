@@ -1,207 +1,116 @@
+use actix_session::Session;
 use actix_web::{web, Error, HttpResponse};
-use chrono;
-use chrono::prelude::*;
 use diesel::prelude::*;
 
-use crate::api::time::get_current_time_diff;
-use crate::model::player::{PlayerData, PlayerInventory};
-use crate::model::user::{User, UserInventoryId};
-use crate::share::db::Pool;
-
-/// CHECK FOR DATETIME IF NOT NOW UPDATE !!!
-
-#[derive(Debug, Deserialize)]
-pub struct AuthData {
-    pub email: String,
-    pub password: String,
-}
-#[derive(Debug, Serialize, Deserialize)]
+use crate::api::player::load_player_state;
+use crate::api::token::{query_new_tokens, Tokens};
+use crate::model::player::{EnergyStatus, PlayerData};
+use crate::model::user::{AuthData, User};
+use crate::share::csrf::csrf_cookie;
+use crate::share::rate_limit::{login_failed, login_locked, login_succeeded};
+use crate::share::utils::{hash_password, is_hashed, random_token, verify};
+use crate::share::{
+    db::{lower, Pool},
+    errors::ServiceError,
+};
+
+#[derive(Debug, Serialize)]
 struct UserWithData {
     pub id: uuid::Uuid,
     pub email: String,
     pub username: String,
-    pub password: String,
     pub player_data: PlayerData,
+    pub energy_status: EnergyStatus,
 }
 
-impl
-    From<(
-        uuid::Uuid,
-        String,
-        String,
-        String,
-        uuid::Uuid,
-        i32,
-        i32,
-        i32,
-        NaiveDateTime,
-        i32,
-        uuid::Uuid,
-        uuid::Uuid,
-    )> for UserWithData
-{
-    fn from(
-        tup: (
-            uuid::Uuid,
-            String,
-            String,
-            String,
-            uuid::Uuid,
-            i32,
-            i32,
-            i32,
-            NaiveDateTime,
-            i32,
-            uuid::Uuid,
-            uuid::Uuid,
-        ),
-    ) -> UserWithData {
-        UserWithData {
-            id: tup.0,
-            email: tup.1,
-            username: tup.2,
-            password: tup.3,
-            player_data: PlayerData {
-                id: tup.4,
-                energy: tup.5,
-                gold: tup.6,
-                exp: tup.7,
-                last_updated: tup.8,
-                gold_acc: tup.9,
-                player_inventory_id: tup.10,
-                player_stats_id: tup.11,
-            },
-        }
-    }
+#[derive(Debug, Serialize)]
+struct LoginResponse {
+    #[serde(flatten)]
+    user: UserWithData,
+    #[serde(flatten)]
+    tokens: Tokens,
 }
 
-impl UserWithData {
-    fn remove_pass(mut self) -> Self {
-        self.password = "".to_owned();
-        self
-    }
+fn query_login(auth_data: AuthData, pool: web::Data<Pool>) -> Result<UserWithData, ServiceError> {
+    use crate::schema::users::dsl::{email, password, users};
+    let conn: &PgConnection = &*pool.get()?;
 
-    fn update_gold(mut self) -> Self {
-        self.player_data.gold = self.player_data.gold
-            + get_current_time_diff(self.player_data.last_updated) * self.player_data.gold_acc;
-        self
-    }
+    let user: User = users
+        .filter(lower(email).eq(&auth_data.email))
+        .first(conn)
+        .optional()?
+        .ok_or(ServiceError::Unauthorized)?;
 
-    fn new_gold(&self) -> i32 {
-        self.player_data.gold
-            + get_current_time_diff(self.player_data.last_updated) * self.player_data.gold_acc
+    if !verify(&user.password, &auth_data.password)? {
+        return Err(ServiceError::Unauthorized);
     }
-}
 
-fn query_login(auth_data: AuthData, pool: web::Data<Pool>) -> Result<UserWithData, ()> {
-    use crate::schema::players_data::dsl::{
-        energy, exp, gold, gold_acc, last_updated, player_inventory_id, player_stats_id,
-        players_data,
-    };
-    use crate::schema::users::dsl::{email, id, password, username, users};
-    let conn: &PgConnection = &pool.get().unwrap();
-
-    // get invetory and stats
-    // check capacity
-
-    let item: UserWithData = users
-        .inner_join(players_data)
-        .select((
-            id,
-            email,
-            username,
-            password,
-            players_data.primary_key(),
-            energy,
-            exp,
-            gold,
-            last_updated,
-            gold_acc,
-            player_inventory_id,
-            player_stats_id,
-        ))
-        .filter(email.eq(&auth_data.email))
-        .get_result::<(
-            uuid::Uuid,
-            String,
-            String,
-            String,
-            uuid::Uuid,
-            i32,
-            i32,
-            i32,
-            NaiveDateTime,
-            i32,
-            uuid::Uuid,
-            uuid::Uuid,
-        )>(conn)
-        .expect("User With Data Error (Join)")
-        .into();
-
-    diesel::update(players_data)
-        .set((
-            gold.eq(item.new_gold()),
-            last_updated.eq(chrono::Utc::now().naive_utc()),
-        ))
-        .execute(conn)
-        .expect("Updating player data (Login)");
-
-    match item.password == auth_data.password {
-        true => Ok(item.update_gold().remove_pass()),
-        false => Err(()),
+    // plaintext password from before hashing, store it hashed from now on
+    if !is_hashed(&user.password) {
+        diesel::update(users.find(&user.id))
+            .set(password.eq(hash_password(&auth_data.password)?))
+            .execute(conn)?;
     }
+
+    let (player_data, stats) = load_player_state(conn, user.id)?;
+
+    Ok(UserWithData {
+        id: user.id,
+        email: user.email,
+        username: user.username,
+        energy_status: player_data.energy_status(&stats),
+        player_data,
+    })
 }
 
-fn query_list(pool: web::Data<Pool>) -> Result<Vec<User>, diesel::result::Error> {
+fn query_list(pool: web::Data<Pool>) -> Result<Vec<User>, ServiceError> {
     use crate::schema::users::dsl::*;
-    let conn: &PgConnection = &pool.get().unwrap();
+    let conn: &PgConnection = &*pool.get()?;
 
-    let items = users.load::<User>(conn).expect("Loading users list");
+    let items = users.load::<User>(conn)?;
     Ok(items)
 }
 
 pub async fn login_user(
     user: web::Json<AuthData>,
     pool: web::Data<Pool>,
+    session: Session,
 ) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || query_login(user.into_inner(), pool))
-        .await
-        .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .expect("General login_user error?"))
-}
+    let auth_data = user.into_inner().validate()?;
+    let login_email = auth_data.email.clone();
+    if let Some(retry_after) = login_locked(&login_email) {
+        return Err(ServiceError::TooManyRequests(retry_after).into());
+    }
 
-pub async fn get_user(pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || query_list(pool))
+    let token_pool = pool.clone();
+    let user = match web::block(move || query_login(auth_data, pool))
         .await
-        .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .expect("Get_user general"))
-}
-
-///USER INVENTORY
-fn query_user_inventory(
-    user: web::Json<UserInventoryId>,
-    pool: web::Data<Pool>,
-) -> Result<PlayerInventory, diesel::result::Error> {
-    use crate::schema::player_inventory::dsl::player_inventory;
-    let conn: &PgConnection = &pool.get().unwrap();
-    dbg!(&user);
-    let items = player_inventory
-        .find(user.inventory_id)
-        .first(conn)
-        .expect("No Inventory Found");
+        .map_err(ServiceError::from)
+    {
+        Err(ServiceError::Unauthorized) => {
+            login_failed(&login_email);
+            return Err(ServiceError::Unauthorized.into());
+        }
+        result => result?,
+    };
+    login_succeeded(&login_email);
 
-    Ok(items)
+    let user_id = user.id;
+    let tokens = web::block(move || query_new_tokens(user_id, token_pool))
+        .await
+        .map_err(ServiceError::from)?;
+
+    session.renew();
+    session.set("user_id", user.id)?;
+    session.set("session_version", tokens.session_version)?;
+    Ok(HttpResponse::Ok()
+        .cookie(csrf_cookie(random_token()))
+        .json(LoginResponse { user, tokens }))
 }
 
-pub async fn get_player_inventory(
-    user: web::Json<UserInventoryId>,
-    pool: web::Data<Pool>,
-) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || query_user_inventory(user, pool))
+pub async fn get_user(pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_list(pool))
         .await
         .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .expect("Get_player inventory general"))
+        .map_err(ServiceError::from)?)
 }
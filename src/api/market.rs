@@ -0,0 +1,384 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{
+        add_gold, add_item, free_capacity, item_by_code, item_count, load_player_data,
+        lock_inventory,
+    },
+};
+use crate::model::market::{market_fee, MarketOrder, NewOrder, OrderStatus, Side};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
+
+/// one matched slice of an order, always at the resting order's price
+#[derive(Debug, Serialize)]
+pub struct Fill {
+    pub order_id: uuid::Uuid,
+    pub price: i32,
+    pub quantity: i32,
+    pub fee: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Placement {
+    pub order: MarketOrder,
+    pub fills: Vec<Fill>,
+}
+
+/// open quantity at one price
+#[derive(Debug, Serialize)]
+pub struct BookLevel {
+    pub price: i32,
+    pub quantity: i64,
+    pub orders: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderBook {
+    pub good: String,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+}
+
+fn query_place_order(
+    user: LoggedUser,
+    new_order: NewOrder,
+    pool: web::Data<Pool>,
+) -> Result<Placement, ServiceError> {
+    use crate::schema::market_orders::dsl::{
+        created_on, good, market_orders, price, remaining, side, status, user_id,
+    };
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let item = item_by_code(conn, &new_order.good)?;
+        let resting = || {
+            market_orders
+                .filter(good.eq(&item.code))
+                .filter(side.eq(new_order.side.opposite().as_str()))
+                .filter(status.eq(OrderStatus::Open.as_str()))
+                .filter(user_id.ne(&user.id))
+        };
+
+        // the placer and everyone they can fill against are locked in id order,
+        // so two crossing orders can't deadlock
+        let makers: Vec<uuid::Uuid> = match new_order.side {
+            Side::Buy => resting()
+                .filter(price.le(new_order.price))
+                .select(user_id)
+                .distinct()
+                .load(conn)?,
+            Side::Sell => resting()
+                .filter(price.ge(new_order.price))
+                .select(user_id)
+                .distinct()
+                .load(conn)?,
+        };
+        let mut players = BTreeMap::new();
+        let ids: BTreeSet<uuid::Uuid> = makers.iter().copied().chain(Some(user.id)).collect();
+        for id in ids {
+            players.insert(id, load_player_data(conn, id)?);
+        }
+        let player_data = &players[&user.id];
+        let inventory = lock_inventory(conn, player_data)?;
+
+        // 1. escrow what the order offers
+        let reason = format!(
+            "{} order for {} {} at {}",
+            new_order.side.as_str(),
+            new_order.quantity,
+            item.code,
+            new_order.price
+        );
+        match new_order.side {
+            Side::Buy => {
+                if player_data.gold < new_order.total() {
+                    return Err(ServiceError::BadRequest("Not enough gold".to_owned()));
+                }
+                if free_capacity(conn, &inventory)? < new_order.quantity * item.weight {
+                    return Err(ServiceError::BadRequest(
+                        "Not enough storage space".to_owned(),
+                    ));
+                }
+                add_gold(conn, user.id, -new_order.total(), "market_order", &reason)?;
+            }
+            Side::Sell => {
+                if item_count(conn, &inventory, &item)? < new_order.quantity {
+                    return Err(ServiceError::BadRequest(format!(
+                        "Not enough {}",
+                        item.name
+                    )));
+                }
+                add_item(
+                    conn,
+                    user.id,
+                    &item,
+                    -new_order.quantity,
+                    "market_order",
+                    &reason,
+                )?;
+            }
+        }
+
+        let mut order = MarketOrder {
+            id: uuid::Uuid::new_v4(),
+            user_id: user.id,
+            side: new_order.side.as_str().to_owned(),
+            good: item.code.clone(),
+            price: new_order.price,
+            quantity: new_order.quantity,
+            remaining: new_order.quantity,
+            status: OrderStatus::Open.as_str().to_owned(),
+            created_on: chrono::Utc::now().naive_utc(),
+        };
+
+        // 2. match against the best resting orders of the locked players, oldest first per price
+        let resting: Vec<MarketOrder> = match new_order.side {
+            Side::Buy => resting()
+                .filter(user_id.eq_any(&makers))
+                .filter(price.le(new_order.price))
+                .order((price.asc(), created_on.asc()))
+                .for_update()
+                .load(conn)?,
+            Side::Sell => resting()
+                .filter(user_id.eq_any(&makers))
+                .filter(price.ge(new_order.price))
+                .order((price.desc(), created_on.asc()))
+                .for_update()
+                .load(conn)?,
+        };
+
+        let mut fills = vec![];
+        for maker in resting {
+            if order.remaining == 0 {
+                break;
+            }
+            let mut quantity = order.remaining.min(maker.remaining);
+            // a resting buyer only gets what still fits in their storage
+            let mut out_of_room = false;
+            if new_order.side == Side::Sell && item.weight > 0 {
+                let maker_inventory = lock_inventory(conn, &players[&maker.user_id])?;
+                let fits = free_capacity(conn, &maker_inventory)? / item.weight;
+                if fits < quantity {
+                    quantity = fits;
+                    out_of_room = true;
+                }
+            }
+
+            if quantity > 0 {
+                let gross = maker.price * quantity;
+                let fee = market_fee(gross);
+                let (buyer, seller) = match new_order.side {
+                    Side::Buy => (user.id, maker.user_id),
+                    Side::Sell => (maker.user_id, user.id),
+                };
+
+                let reason = format!("{} {} at {}, fee {}", quantity, item.code, maker.price, fee);
+                add_item(conn, buyer, &item, quantity, "market_fill", &reason)?;
+                add_gold(conn, seller, gross - fee, "market_fill", &reason)?;
+                // a buyer escrowed at their own limit, give back what the cheaper fill saved
+                if new_order.side == Side::Buy && new_order.price > maker.price {
+                    add_gold(
+                        conn,
+                        user.id,
+                        (new_order.price - maker.price) * quantity,
+                        "market_refund",
+                        &reason,
+                    )?;
+                }
+                order.remaining -= quantity;
+                fills.push(Fill {
+                    order_id: maker.id,
+                    price: maker.price,
+                    quantity,
+                    fee,
+                });
+            }
+
+            // a buyer without room would block the book, the rest of their order is cancelled
+            let maker_remaining = maker.remaining - quantity;
+            let maker_status = match maker_remaining {
+                0 => OrderStatus::Filled,
+                _ if out_of_room => {
+                    add_gold(
+                        conn,
+                        maker.user_id,
+                        maker.price * maker_remaining,
+                        "market_cancel",
+                        &format!("order {} cancelled, no storage space", maker.id),
+                    )?;
+                    OrderStatus::Cancelled
+                }
+                _ => OrderStatus::Open,
+            };
+            diesel::update(market_orders.find(&maker.id))
+                .set((
+                    remaining.eq(maker_remaining),
+                    status.eq(maker_status.as_str()),
+                ))
+                .execute(conn)?;
+        }
+
+        if order.remaining == 0 {
+            order.status = OrderStatus::Filled.as_str().to_owned();
+        }
+        let order = diesel::insert_into(market_orders)
+            .values(&order)
+            .get_result(conn)?;
+
+        Ok(Placement { order, fills })
+    })
+}
+
+/// escrow gold or goods, fill against the book, the rest stays open
+pub async fn place_order(
+    user: LoggedUser,
+    new_order: web::Json<NewOrder>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let new_order = new_order.into_inner().validate()?;
+    Ok(web::block(move || query_place_order(user, new_order, pool))
+        .await
+        .map(|placement| HttpResponse::Ok().json(placement))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_cancel_order(
+    user: LoggedUser,
+    order_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<MarketOrder, ServiceError> {
+    use crate::schema::market_orders::dsl::{market_orders, status, user_id};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        load_player_data(conn, user.id)?;
+
+        let order: MarketOrder = market_orders
+            .find(&order_id)
+            .filter(user_id.eq(&user.id))
+            .for_update()
+            .first(conn)
+            .or_not_found("Order")?;
+        if order.status != OrderStatus::Open.as_str() {
+            return Err(ServiceError::BadRequest("Order is not open".to_owned()));
+        }
+
+        let reason = format!("cancelled order {}", order.id);
+        let item = item_by_code(conn, &order.good)?;
+        match order.side.as_str() {
+            "buy" => add_gold(
+                conn,
+                user.id,
+                order.price * order.remaining,
+                "market_cancel",
+                &reason,
+            )?,
+            _ => add_item(
+                conn,
+                user.id,
+                &item,
+                order.remaining,
+                "market_cancel",
+                &reason,
+            )?,
+        };
+
+        let cancelled = diesel::update(market_orders.find(&order.id))
+            .set(status.eq(OrderStatus::Cancelled.as_str()))
+            .get_result(conn)?;
+        Ok(cancelled)
+    })
+}
+
+/// cancel an open order, the unfilled escrow goes back
+pub async fn cancel_order(
+    user: LoggedUser,
+    order_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_cancel_order(user, order_id.into_inner(), pool))
+            .await
+            .map(|order| HttpResponse::Ok().json(order))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+fn query_open_orders(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<Vec<MarketOrder>, ServiceError> {
+    use crate::schema::market_orders::dsl::{created_on, market_orders, status, user_id};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let items = market_orders
+        .filter(user_id.eq(&user.id))
+        .filter(status.eq(OrderStatus::Open.as_str()))
+        .order(created_on.desc())
+        .load::<MarketOrder>(conn)?;
+    Ok(items)
+}
+
+/// the logged in player's open orders
+pub async fn get_open_orders(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_open_orders(user, pool))
+        .await
+        .map(|orders| HttpResponse::Ok().json(orders))
+        .map_err(ServiceError::from)?)
+}
+
+fn levels(orders: &[MarketOrder], side_name: &str) -> BTreeMap<i32, BookLevel> {
+    let mut levels = BTreeMap::new();
+    for order in orders.iter().filter(|o| o.side == side_name) {
+        let level = levels.entry(order.price).or_insert(BookLevel {
+            price: order.price,
+            quantity: 0,
+            orders: 0,
+        });
+        level.quantity += i64::from(order.remaining);
+        level.orders += 1;
+    }
+    levels
+}
+
+fn query_order_book(book_good: String, pool: web::Data<Pool>) -> Result<OrderBook, ServiceError> {
+    use crate::schema::market_orders::dsl::{good, market_orders, status};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let orders = market_orders
+        .filter(good.eq(&book_good))
+        .filter(status.eq(OrderStatus::Open.as_str()))
+        .load::<MarketOrder>(conn)?;
+
+    Ok(OrderBook {
+        good: book_good,
+        // best price first on both sides
+        bids: levels(&orders, Side::Buy.as_str())
+            .into_values()
+            .rev()
+            .collect(),
+        asks: levels(&orders, Side::Sell.as_str()).into_values().collect(),
+    })
+}
+
+/// open buy and sell quantity per price for one good
+pub async fn get_order_book(
+    book_good: web::Path<String>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_order_book(book_good.into_inner(), pool))
+            .await
+            .map(|book| HttpResponse::Ok().json(book))
+            .map_err(ServiceError::from)?,
+    )
+}
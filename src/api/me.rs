@@ -0,0 +1,152 @@
+use actix_web::{web, Error, HttpResponse};
+use chrono::prelude::*;
+use diesel::prelude::*;
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{inventory_view, load_player_data, load_player_state, InventoryView},
+    production::owned_factories,
+};
+use crate::model::{
+    player::{EnergyStatus, PlayerStats},
+    user::User,
+};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
+
+/// one owned factory with what it makes and what is waiting to be collected
+#[derive(Debug, Serialize)]
+pub struct OwnedFactoryView {
+    pub id: uuid::Uuid,
+    pub factory_id: uuid::Uuid,
+    pub name: String,
+    pub level: i32,
+    pub item: String,
+    pub amount: i32,
+    pub gold_per_day: i32,
+    pub per_day: i32,
+    pub pending: i32,
+    pub produced_at: NaiveDateTime,
+}
+
+/// everything about the logged in player in one response
+#[derive(Debug, Serialize)]
+pub struct MeView {
+    pub id: uuid::Uuid,
+    pub email: String,
+    pub username: String,
+    pub role: String,
+    pub created_on: NaiveDateTime,
+    pub gold: i32,
+    pub gold_acc: i32,
+    pub exp: i32,
+    pub energy: EnergyStatus,
+    pub stats: PlayerStats,
+    pub inventory: InventoryView,
+    pub factories: Vec<OwnedFactoryView>,
+}
+
+fn factories_view(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+) -> Result<Vec<OwnedFactoryView>, ServiceError> {
+    let now = chrono::Utc::now().naive_utc();
+
+    Ok(owned_factories(conn, player_id)?
+        .into_iter()
+        .map(|(owned, (factory, item))| OwnedFactoryView {
+            pending: owned.pending_production(&factory, now).0,
+            per_day: factory.product_amount.saturating_mul(owned.amount),
+            gold_per_day: factory.gold_per_day.saturating_mul(owned.amount),
+            id: owned.id,
+            factory_id: factory.id,
+            name: factory.name,
+            level: factory.level,
+            item: item.code,
+            amount: owned.amount,
+            produced_at: owned.produced_at,
+        })
+        .collect())
+}
+
+/// users.player_data_id => players_data => stats and inventory, all for the token's user
+fn query_me(user: LoggedUser, pool: web::Data<Pool>) -> Result<MeView, ServiceError> {
+    use crate::schema::users::dsl::users;
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player: User = users.find(&user.id).first(conn).or_not_found("Player")?;
+        let (player_data, stats) = load_player_state(conn, user.id)?;
+
+        Ok(MeView {
+            id: player.id,
+            email: player.email,
+            username: player.username,
+            role: player.role,
+            created_on: player.created_on,
+            gold: player_data.gold,
+            gold_acc: player_data.gold_acc,
+            exp: player_data.exp,
+            energy: player_data.energy_status(&stats),
+            inventory: inventory_view(conn, &player_data)?,
+            factories: factories_view(conn, user.id)?,
+            stats,
+        })
+    })
+}
+
+/// profile, resources, stats, inventory and factories of the logged in player
+pub async fn get_me(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_me(user, pool))
+        .await
+        .map(|me| HttpResponse::Ok().json(me))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_my_inventory(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<InventoryView, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        inventory_view(conn, &player_data)
+    })
+}
+
+/// the logged in player's inventory
+pub async fn get_my_inventory(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_my_inventory(user, pool))
+        .await
+        .map(|inventory| HttpResponse::Ok().json(inventory))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_my_factories(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<Vec<OwnedFactoryView>, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        load_player_data(conn, user.id)?;
+        factories_view(conn, user.id)
+    })
+}
+
+/// the logged in player's factories
+pub async fn get_my_factories(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_my_factories(user, pool))
+        .await
+        .map(|owned| HttpResponse::Ok().json(owned))
+        .map_err(ServiceError::from)?)
+}
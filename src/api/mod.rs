@@ -1,7 +1,19 @@
 pub mod auth;
 pub mod battle;
+pub mod crafting;
 pub mod factories;
 pub mod invitation;
+pub mod items;
+pub mod ledger;
 pub mod login;
+pub mod market;
+pub mod me;
+pub mod password_reset;
+pub mod player;
+pub mod production;
 pub mod register;
+pub mod shop;
+pub mod storage;
 pub mod time;
+pub mod token;
+pub mod trade;
@@ -0,0 +1,116 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::{prelude::*, PgConnection};
+use std::sync::Arc;
+
+use crate::model::password_reset::PasswordReset;
+use crate::share::{
+    db::{lower, Pool},
+    email::{password_reset_email, Mailer},
+    errors::ServiceError,
+    utils::hash_password,
+    validation::{check_password, normalize_email, ValidationErrors},
+};
+
+#[derive(Deserialize)]
+pub struct ResetRequestData {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewPasswordData {
+    pub password: String,
+}
+
+fn query_request_reset(
+    eml: String,
+    pool: web::Data<Pool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+) -> Result<(), ServiceError> {
+    use crate::schema::password_resets::dsl::password_resets;
+    use crate::schema::users::dsl::{email, users};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let exists: bool = diesel::select(diesel::dsl::exists(users.filter(lower(email).eq(&eml))))
+        .get_result(conn)?;
+    // same answer for unknown emails, so accounts can't be probed
+    if !exists {
+        return Ok(());
+    }
+
+    let new_reset: PasswordReset = eml.into();
+    let reset: PasswordReset = diesel::insert_into(password_resets)
+        .values(&new_reset)
+        .get_result(conn)?;
+
+    mailer.send(&password_reset_email(&reset))
+}
+
+/// email a one time reset link
+pub async fn request_reset(
+    data: web::Json<ResetRequestData>,
+    pool: web::Data<Pool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+) -> Result<HttpResponse, Error> {
+    let eml = normalize_email(&data.into_inner().email);
+    web::block(move || query_request_reset(eml, pool, mailer))
+        .await
+        .map_err(ServiceError::from)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn query_reset_password(
+    reset_id: uuid::Uuid,
+    new_password: String,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::schema::password_resets::dsl::password_resets;
+    use crate::schema::refresh_tokens::dsl::{refresh_tokens, revoked, user_id};
+    use crate::schema::users::dsl::{email, id, password, session_version, users};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let hashed = hash_password(&new_password)?;
+
+    conn.transaction(|| {
+        // deleting the row consumes the token, a second use finds nothing
+        let reset: PasswordReset = diesel::delete(password_resets.find(reset_id))
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| ServiceError::BadRequest("Invalid reset link".into()))?;
+        if reset.is_expired() {
+            return Err(ServiceError::BadRequest("Reset link expired".into()));
+        }
+
+        let user: uuid::Uuid =
+            diesel::update(users.filter(lower(email).eq(normalize_email(&reset.email))))
+                .set((password.eq(hashed), session_version.eq(session_version + 1)))
+                .returning(id)
+                .get_result(conn)?;
+
+        diesel::update(refresh_tokens.filter(user_id.eq(user)))
+            .set(revoked.eq(true))
+            .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// consume reset token => new password, every existing session and token is invalidated
+pub async fn reset_password(
+    reset_id: web::Path<uuid::Uuid>,
+    data: web::Json<NewPasswordData>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let new_password = data.into_inner().password;
+    let mut errors = ValidationErrors::default();
+    check_password(&new_password, &mut errors);
+    if !errors.is_empty() {
+        return Err(ServiceError::Validation(errors).into());
+    }
+
+    web::block(move || query_reset_password(reset_id.into_inner(), new_password, pool))
+        .await
+        .map_err(ServiceError::from)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
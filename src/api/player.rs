@@ -0,0 +1,485 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::prelude::*;
+
+use crate::api::{auth::LoggedUser, ledger::record};
+use crate::model::{
+    item::{Item, Meal, PlayerItem, FOOD_PER_HOUR},
+    ledger::{LedgerEntry, Resource},
+    market::{OrderStatus, Side},
+    player::{EnergyStatus, PlayerData, PlayerInventory, PlayerStats},
+    trade::{TradeSide, TradeStatus},
+    user::User,
+};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
+
+/// every read of player state goes through here, so idle gold and energy are
+/// settled before anything looks at them or changes `gold_acc`
+pub fn load_player_state(
+    conn: &PgConnection,
+    user_id: uuid::Uuid,
+) -> Result<(PlayerData, PlayerStats), ServiceError> {
+    use crate::schema::player_stats::dsl::player_stats;
+    use crate::schema::players_data::dsl::{
+        energy, energy_updated_at, gold, last_updated, players_data,
+    };
+    use crate::schema::users::dsl::users;
+
+    conn.transaction(|| {
+        let player: User = users.find(&user_id).first(conn).or_not_found("Player")?;
+        let mut player_data: PlayerData = players_data
+            .find(&player.player_data_id)
+            .for_update()
+            .first(conn)
+            .or_not_found("Player data")?;
+        let stats: PlayerStats = player_stats
+            .find(&player_data.player_stats_id)
+            .first(conn)
+            .or_not_found("Player stats")?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let gold_before = player_data.gold;
+        let gold_changed = player_data.accrue_gold(now);
+        let energy_changed = player_data.regen_energy(&stats, now);
+        if gold_changed || energy_changed {
+            diesel::update(players_data.find(&player_data.id))
+                .set((
+                    gold.eq(player_data.gold),
+                    last_updated.eq(player_data.last_updated),
+                    energy.eq(player_data.energy),
+                    energy_updated_at.eq(player_data.energy_updated_at),
+                ))
+                .execute(conn)?;
+        }
+        record(
+            conn,
+            vec![LedgerEntry::new(
+                user_id,
+                Resource::Gold,
+                player_data.gold - gold_before,
+                player_data.gold,
+                "idle_income",
+                "gold per day from owned factories",
+            )],
+        )?;
+
+        Ok((player_data, stats))
+    })
+}
+
+pub fn load_player_data(
+    conn: &PgConnection,
+    user_id: uuid::Uuid,
+) -> Result<PlayerData, ServiceError> {
+    load_player_state(conn, user_id).map(|(player_data, _)| player_data)
+}
+
+/// inventory row locked until the surrounding transaction ends
+pub fn lock_inventory(
+    conn: &PgConnection,
+    player_data: &PlayerData,
+) -> Result<PlayerInventory, ServiceError> {
+    use crate::schema::player_inventory::dsl::player_inventory;
+
+    player_inventory
+        .find(&player_data.player_inventory_id)
+        .for_update()
+        .first(conn)
+        .or_not_found("Inventory")
+}
+
+/// credit (negative debits) any player's gold inside the caller's transaction
+pub fn add_gold(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    delta: i32,
+    action: &str,
+    reason: &str,
+) -> Result<i32, ServiceError> {
+    use crate::schema::players_data::dsl::{gold, players_data};
+    use crate::schema::users::dsl::{player_data_id, users};
+
+    let data_id: uuid::Uuid = users
+        .find(&player_id)
+        .select(player_data_id)
+        .first(conn)
+        .or_not_found("Player")?;
+    let updated: PlayerData = diesel::update(players_data.find(&data_id))
+        .set(gold.eq(gold + delta))
+        .get_result(conn)?;
+
+    record(
+        conn,
+        vec![LedgerEntry::new(
+            player_id,
+            Resource::Gold,
+            delta,
+            updated.gold,
+            action,
+            reason,
+        )],
+    )?;
+    Ok(updated.gold)
+}
+
+/// credit (negative debits) any player's special_currency inside the caller's transaction
+pub fn add_special_currency(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    delta: i32,
+    action: &str,
+    reason: &str,
+) -> Result<i32, ServiceError> {
+    use crate::schema::player_inventory::dsl::{player_inventory, special_currency};
+    use crate::schema::players_data::dsl::{player_inventory_id, players_data};
+    use crate::schema::users::dsl::{id, users};
+
+    let inventory_id: uuid::Uuid = users
+        .inner_join(players_data)
+        .filter(id.eq(&player_id))
+        .select(player_inventory_id)
+        .first(conn)
+        .or_not_found("Player")?;
+    let updated: PlayerInventory = diesel::update(player_inventory.find(&inventory_id))
+        .set(special_currency.eq(special_currency + delta))
+        .get_result(conn)?;
+
+    record(
+        conn,
+        vec![LedgerEntry::new(
+            player_id,
+            Resource::SpecialCurrency,
+            delta,
+            updated.special_currency,
+            action,
+            reason,
+        )],
+    )?;
+    Ok(updated.special_currency)
+}
+
+/// one stack as clients see it
+#[derive(Debug, Serialize)]
+pub struct InventoryItem {
+    pub code: String,
+    pub name: String,
+    pub item_type: String,
+    pub quality: i32,
+    pub stack_size: i32,
+    pub weight: i32,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InventoryView {
+    pub id: uuid::Uuid,
+    pub capacity: i32,
+    pub free_capacity: i32,
+    pub special_currency: i32,
+    pub storage_level: i32,
+    pub items: Vec<InventoryItem>,
+}
+
+pub fn inventory_view(
+    conn: &PgConnection,
+    player_data: &PlayerData,
+) -> Result<InventoryView, ServiceError> {
+    let inventory = lock_inventory(conn, player_data)?;
+    let items = inventory_items(conn, inventory.id)?
+        .into_iter()
+        .map(|(owned, item)| InventoryItem {
+            code: item.code,
+            name: item.name,
+            item_type: item.item_type,
+            quality: item.quality,
+            stack_size: item.stack_size,
+            weight: item.weight,
+            quantity: owned.quantity,
+        })
+        .collect();
+
+    Ok(InventoryView {
+        free_capacity: free_capacity(conn, &inventory)?,
+        id: inventory.id,
+        capacity: inventory.capacity,
+        special_currency: inventory.special_currency,
+        storage_level: inventory.storage_level,
+        items,
+    })
+}
+
+pub fn item_by_code(conn: &PgConnection, item_code: &str) -> Result<Item, ServiceError> {
+    use crate::schema::items::dsl::{code, items};
+
+    items
+        .filter(code.eq(item_code))
+        .first(conn)
+        .or_not_found("Item")
+}
+
+/// every item row of an inventory with its catalog entry, empty stacks left out
+pub fn inventory_items(
+    conn: &PgConnection,
+    inventory_id: uuid::Uuid,
+) -> Result<Vec<(PlayerItem, Item)>, ServiceError> {
+    use crate::schema::items::dsl::{code, items};
+    use crate::schema::player_items::dsl::{player_inventory_id, player_items, quantity};
+
+    Ok(player_items
+        .inner_join(items)
+        .filter(player_inventory_id.eq(&inventory_id))
+        .filter(quantity.gt(0))
+        .order(code)
+        .load(conn)?)
+}
+
+/// capacity counts item weight, not pieces, items held in escrow by pending
+/// trade offers and open sell orders keep their room so a refund always fits
+pub fn free_capacity(
+    conn: &PgConnection,
+    inventory: &PlayerInventory,
+) -> Result<i32, ServiceError> {
+    let used: i64 = inventory_items(conn, inventory.id)?
+        .iter()
+        .map(|(owned, item)| i64::from(owned.quantity) * i64::from(item.weight))
+        .sum();
+    let reserved = escrowed_weight(conn, inventory)?;
+    Ok((i64::from(inventory.capacity) - used - reserved).max(0) as i32)
+}
+
+/// weight of the items the inventory's owner offered in pending trades or
+/// still has listed in open sell orders
+fn escrowed_weight(conn: &PgConnection, inventory: &PlayerInventory) -> Result<i64, ServiceError> {
+    use crate::schema::items::dsl::{code, items, weight};
+    use crate::schema::market_orders::dsl::{good, market_orders, remaining, user_id};
+    use crate::schema::players_data::dsl::{player_inventory_id, players_data};
+    use crate::schema::trade_offer_items::dsl::{quantity, side, trade_offer_items};
+    use crate::schema::trade_offers::dsl::{sender_id, status, trade_offers};
+    use crate::schema::users::dsl::{id, users};
+
+    let owner: uuid::Uuid = users
+        .inner_join(players_data)
+        .filter(player_inventory_id.eq(&inventory.id))
+        .select(id)
+        .first(conn)
+        .or_not_found("Player")?;
+    let lines: Vec<(i32, i32)> = trade_offer_items
+        .inner_join(items)
+        .inner_join(trade_offers)
+        .filter(sender_id.eq(&owner))
+        .filter(status.eq(TradeStatus::Pending.as_str()))
+        .filter(side.eq(TradeSide::Offered.as_str()))
+        .select((quantity, weight))
+        .load(conn)?;
+    let listed: Vec<(i32, i32)> = market_orders
+        .inner_join(items.on(code.eq(good)))
+        .filter(user_id.eq(&owner))
+        .filter(crate::schema::market_orders::side.eq(Side::Sell.as_str()))
+        .filter(crate::schema::market_orders::status.eq(OrderStatus::Open.as_str()))
+        .select((remaining, weight))
+        .load(conn)?;
+    Ok(lines
+        .iter()
+        .chain(listed.iter())
+        .map(|(pieces, each)| i64::from(*pieces) * i64::from(*each))
+        .sum())
+}
+
+pub fn item_count(
+    conn: &PgConnection,
+    inventory: &PlayerInventory,
+    item: &Item,
+) -> Result<i32, ServiceError> {
+    use crate::schema::player_items::dsl::{item_id, player_inventory_id, player_items, quantity};
+
+    Ok(player_items
+        .filter(player_inventory_id.eq(&inventory.id))
+        .filter(item_id.eq(&item.id))
+        .select(quantity)
+        .first(conn)
+        .optional()?
+        .unwrap_or(0))
+}
+
+/// credit (negative debits) any player's items inside the caller's transaction
+pub fn add_item(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    item: &Item,
+    delta: i32,
+    action: &str,
+    reason: &str,
+) -> Result<i32, ServiceError> {
+    use crate::schema::player_items::dsl::{item_id, player_inventory_id, player_items, quantity};
+    use crate::schema::players_data::dsl::{player_inventory_id as inventory_of, players_data};
+    use crate::schema::users::dsl::{id, users};
+
+    let inventory_id: uuid::Uuid = users
+        .inner_join(players_data)
+        .filter(id.eq(&player_id))
+        .select(inventory_of)
+        .first(conn)
+        .or_not_found("Player")?;
+
+    let new_stack = PlayerItem {
+        id: uuid::Uuid::new_v4(),
+        player_inventory_id: inventory_id,
+        item_id: item.id,
+        quantity: delta,
+    };
+    let updated: PlayerItem = diesel::insert_into(player_items)
+        .values(&new_stack)
+        .on_conflict((player_inventory_id, item_id))
+        .do_update()
+        .set(quantity.eq(quantity + delta))
+        .get_result(conn)?;
+
+    record(
+        conn,
+        vec![LedgerEntry::new(
+            player_id,
+            Resource::Item(item.code.clone()),
+            delta,
+            updated.quantity,
+            action,
+            reason,
+        )],
+    )?;
+    Ok(updated.quantity)
+}
+
+fn query_energy(user: LoggedUser, pool: web::Data<Pool>) -> Result<EnergyStatus, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    let (player_data, stats) = load_player_state(conn, user.id)?;
+    Ok(player_data.energy_status(&stats))
+}
+
+/// current energy, cap and when the next point comes in
+pub async fn get_energy(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_energy(user, pool))
+        .await
+        .map(|energy| HttpResponse::Ok().json(energy))
+        .map_err(ServiceError::from)?)
+}
+
+/// what a meal did, only as many pieces as fit under the energy cap are eaten
+#[derive(Debug, Serialize)]
+pub struct MealResult {
+    pub item: String,
+    pub eaten: i32,
+    pub restored: i32,
+    pub eaten_this_hour: i32,
+    pub energy: EnergyStatus,
+}
+
+/// food eaten in the last hour, read back from the ledger
+fn eaten_since(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    since: chrono::NaiveDateTime,
+) -> Result<i32, ServiceError> {
+    use crate::schema::ledger_entries::dsl::{action, created_on, delta, ledger_entries, user_id};
+
+    let total: Option<i64> = ledger_entries
+        .filter(user_id.eq(&player_id))
+        .filter(action.eq("eat"))
+        .filter(created_on.ge(since))
+        .select(diesel::dsl::sum(delta))
+        .first(conn)?;
+    Ok(-total.unwrap_or(0) as i32)
+}
+
+fn query_eat(
+    user: LoggedUser,
+    meal: Meal,
+    pool: web::Data<Pool>,
+) -> Result<MealResult, ServiceError> {
+    use crate::schema::players_data::dsl::{energy, energy_updated_at, players_data};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let (mut player_data, stats) = load_player_state(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        let item = item_by_code(conn, &meal.item)?;
+        let restore = match item.energy_restore() {
+            Some(restore) => restore,
+            None => {
+                return Err(ServiceError::BadRequest(format!(
+                    "{} is not food",
+                    item.code
+                )))
+            }
+        };
+
+        let max = stats.max_energy();
+        if player_data.energy >= max {
+            return Err(ServiceError::BadRequest(
+                "Energy is already full".to_owned(),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let eaten_before = eaten_since(conn, user.id, now - chrono::Duration::hours(1))?;
+        let allowance = *FOOD_PER_HOUR - eaten_before;
+        if allowance <= 0 {
+            return Err(ServiceError::BadRequest(
+                "Hourly food limit reached".to_owned(),
+            ));
+        }
+
+        // the last piece may overshoot the cap, eat what is held and leave the rest
+        let missing = max - player_data.energy;
+        let needed = (missing + restore - 1) / restore;
+        let held = item_count(conn, &inventory, &item)?;
+        let eaten = meal.quantity.min(needed).min(allowance).min(held);
+        if eaten == 0 {
+            return Err(ServiceError::BadRequest(format!(
+                "Not enough {}",
+                item.code
+            )));
+        }
+
+        let restored = (eaten * restore).min(missing);
+        player_data.energy += restored;
+        if player_data.energy >= max {
+            player_data.energy_updated_at = now;
+        }
+        diesel::update(players_data.find(&player_data.id))
+            .set((
+                energy.eq(player_data.energy),
+                energy_updated_at.eq(player_data.energy_updated_at),
+            ))
+            .execute(conn)?;
+        add_item(
+            conn,
+            user.id,
+            &item,
+            -eaten,
+            "eat",
+            &format!("restored {} energy", restored),
+        )?;
+
+        Ok(MealResult {
+            item: item.code,
+            eaten,
+            restored,
+            eaten_this_hour: eaten_before + eaten,
+            energy: player_data.energy_status(&stats),
+        })
+    })
+}
+
+/// food => energy, capped at max energy and FOOD_PER_HOUR pieces an hour
+pub async fn eat(
+    user: LoggedUser,
+    meal: web::Json<Meal>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let meal = meal.into_inner().validate()?;
+    Ok(web::block(move || query_eat(user, meal, pool))
+        .await
+        .map(|result| HttpResponse::Ok().json(result))
+        .map_err(ServiceError::from)?)
+}
@@ -0,0 +1,174 @@
+use actix_web::{web, Error, HttpResponse};
+use chrono::prelude::*;
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{add_item, free_capacity, load_player_data, lock_inventory},
+};
+use crate::model::{
+    factory::Factory,
+    item::Item,
+    player::{PlayerData, PlayerFactories},
+};
+use crate::share::{db::Pool, errors::ServiceError};
+
+/// output of one owned factory waiting to be collected
+#[derive(Debug, Serialize)]
+pub struct PendingProduction {
+    pub factory_id: uuid::Uuid,
+    pub name: String,
+    pub item: String,
+    pub amount: i32,
+    pub per_day: i32,
+    pub pending: i32,
+    pub produced_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProductionStatus {
+    pub factories: Vec<PendingProduction>,
+    pub free_capacity: i32,
+}
+
+/// item code => pieces a collect moved into the inventory
+#[derive(Debug, Serialize)]
+pub struct Collection {
+    pub collected: BTreeMap<String, i32>,
+}
+
+/// an owned factory with the factory and the item it makes
+pub type OwnedFactory = (PlayerFactories, (Factory, Item));
+
+/// player rows are locked by `load_player_data`, so these don't need their own lock
+pub fn owned_factories(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+) -> Result<Vec<OwnedFactory>, ServiceError> {
+    use crate::schema::factories::dsl::factories;
+    use crate::schema::items::dsl::items;
+    use crate::schema::player_factories::dsl::{player_factories, user_id};
+
+    Ok(player_factories
+        .inner_join(factories.inner_join(items))
+        .filter(user_id.eq(&player_id))
+        .load(conn)?)
+}
+
+/// move everything owned factories made into the inventory, output that doesn't
+/// fit stays pending, call it inside the transaction of any action that changes ownership
+pub fn collect_production(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    player_data: &PlayerData,
+) -> Result<Collection, ServiceError> {
+    use crate::schema::player_factories::dsl::{player_factories, produced_at};
+
+    let inventory = lock_inventory(conn, player_data)?;
+    let mut free = free_capacity(conn, &inventory)?;
+    let mut collected: BTreeMap<String, (Item, i32)> = BTreeMap::new();
+    let now = chrono::Utc::now().naive_utc();
+
+    for (owned, (factory, item)) in owned_factories(conn, player_id)? {
+        let (units, made_until) = owned.pending_production(&factory, now);
+        if units == 0 && made_until == owned.produced_at {
+            continue;
+        }
+
+        let fits = match item.weight {
+            0 => units,
+            weight => units.min(free / weight),
+        };
+        free -= fits * item.weight;
+        // storage full, the clock only moves past what was stored
+        let made_until = match fits < units {
+            true => owned.time_for(&factory, fits),
+            false => made_until,
+        };
+        diesel::update(player_factories.find(&owned.id))
+            .set(produced_at.eq(made_until))
+            .execute(conn)?;
+
+        if fits > 0 {
+            collected.entry(item.code.clone()).or_insert((item, 0)).1 += fits;
+        }
+    }
+
+    for (item, amount) in collected.values() {
+        add_item(
+            conn,
+            player_id,
+            item,
+            *amount,
+            "collect",
+            "production from owned factories",
+        )?;
+    }
+
+    Ok(Collection {
+        collected: collected
+            .into_iter()
+            .map(|(code, (_, amount))| (code, amount))
+            .collect(),
+    })
+}
+
+fn query_production(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<ProductionStatus, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        let now = chrono::Utc::now().naive_utc();
+
+        let pending = owned_factories(conn, user.id)?
+            .into_iter()
+            .map(|(owned, (factory, item))| PendingProduction {
+                pending: owned.pending_production(&factory, now).0,
+                per_day: factory.product_amount.saturating_mul(owned.amount),
+                factory_id: factory.id,
+                name: factory.name,
+                item: item.code,
+                amount: owned.amount,
+                produced_at: owned.produced_at,
+            })
+            .collect();
+
+        Ok(ProductionStatus {
+            factories: pending,
+            free_capacity: free_capacity(conn, &inventory)?,
+        })
+    })
+}
+
+/// goods waiting in owned factories and the room left for them
+pub async fn get_production(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_production(user, pool))
+        .await
+        .map(|status| HttpResponse::Ok().json(status))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_collect(user: LoggedUser, pool: web::Data<Pool>) -> Result<Collection, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        collect_production(conn, user.id, &player_data)
+    })
+}
+
+/// owned factories => products into inventory, up to its capacity
+pub async fn collect(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_collect(user, pool))
+        .await
+        .map(|collection| HttpResponse::Ok().json(collection))
+        .map_err(ServiceError::from)?)
+}
@@ -1,26 +1,41 @@
 use actix_web::{web, Error, HttpResponse};
 use diesel::prelude::*;
 
+use crate::api::{
+    ledger::record,
+    player::{add_item, item_by_code},
+};
 use crate::model::{
+    invitations::Invitation,
+    ledger::{LedgerEntry, Resource},
     player::{PlayerData, PlayerInventory, PlayerStats},
-    user::{NewUser, User},
+    user::{NewUser, Role, User},
+};
+use crate::share::{
+    db::{lower, Pool},
+    errors::ServiceError,
+    utils::hash_password,
+    validation::normalize_email,
 };
-use crate::share::db::Pool;
 
-fn query(new_user_data: NewUser, pool: web::Data<Pool>) -> Result<User, diesel::result::Error> {
+// new players start with a few rations
+const STARTING_FOOD: &str = "food_q1";
+
+fn query(new_user_data: NewUser, pool: web::Data<Pool>) -> Result<User, ServiceError> {
+    use crate::schema::invitations::dsl::invitations;
     use crate::schema::player_inventory::dsl::player_inventory;
     use crate::schema::player_stats::dsl::player_stats;
     use crate::schema::players_data::dsl::players_data;
-    use crate::schema::users::dsl::{id, users};
+    use crate::schema::users::dsl::{email, id, username, users};
 
-    let conn: &PgConnection = &pool.get().unwrap();
+    let conn: &PgConnection = &*pool.get()?;
 
+    let invitation_id = new_user_data.invitation_id;
     let new_user_inventory = PlayerInventory {
         id: uuid::Uuid::new_v4(),
-        food_q1: 10,
-        weapon_q1: 0,
         capacity: 100,
         special_currency: 0,
+        storage_level: 1,
     };
     let new_user_stats = PlayerStats {
         id: uuid::Uuid::new_v4(),
@@ -38,62 +53,108 @@ fn query(new_user_data: NewUser, pool: web::Data<Pool>) -> Result<User, diesel::
         last_updated: chrono::Utc::now().naive_utc(),
         player_stats_id: new_user_stats.id,
         player_inventory_id: new_user_inventory.id,
+        energy_updated_at: chrono::Utc::now().naive_utc(),
     };
 
     let new_user = User {
         id: uuid::Uuid::new_v4(),
         email: new_user_data.email,
         username: new_user_data.username,
-        password: new_user_data.password,
+        password: hash_password(&new_user_data.password)?,
         created_on: chrono::Utc::now().naive_utc(),
         player_data_id: new_player_data.id,
+        session_version: 0,
+        role: Role::Player.as_str().to_owned(),
     };
 
-    diesel::insert_into(player_inventory)
-        .values(&new_user_inventory)
-        .execute(conn)
-        .expect("player_inv");
-    diesel::insert_into(player_stats)
-        .values(&new_user_stats)
-        .execute(conn)
-        .expect("player_stats");
-    diesel::insert_into(players_data)
-        .values(&new_player_data)
-        .execute(conn)
-        .expect("players_data");
-    diesel::insert_into(users)
-        .values(&new_user)
-        .execute(conn)
-        .expect("users");
-
-    let created_user = users
-        .filter(id.eq(&new_user.id))
-        .get_result(conn)
-        .expect("Returning new user");
-    Ok(created_user)
+    conn.transaction(|| {
+        // locked until the delete below, so an invitation can only be used once
+        let invitation: Invitation = invitations
+            .find(&invitation_id)
+            .for_update()
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| ServiceError::BadRequest("Invalid Invitation".into()))?;
+
+        if normalize_email(&invitation.email) != new_user.email {
+            return Err(ServiceError::BadRequest("Invalid Invitation".into()));
+        }
+        if invitation.is_expired() {
+            return Err(ServiceError::BadRequest("Invitation expired".into()));
+        }
+
+        // checked up front so the client learns which field clashed, the unique index still backs it
+        let email_taken: bool = diesel::select(diesel::dsl::exists(
+            users.filter(lower(email).eq(&new_user.email)),
+        ))
+        .get_result(conn)?;
+        if email_taken {
+            return Err(ServiceError::Conflict("Email already registered".into()));
+        }
+        let username_taken: bool = diesel::select(diesel::dsl::exists(
+            users.filter(username.eq(&new_user.username)),
+        ))
+        .get_result(conn)?;
+        if username_taken {
+            return Err(ServiceError::Conflict("Username already taken".into()));
+        }
+
+        diesel::insert_into(player_inventory)
+            .values(&new_user_inventory)
+            .execute(conn)?;
+        diesel::insert_into(player_stats)
+            .values(&new_user_stats)
+            .execute(conn)?;
+        diesel::insert_into(players_data)
+            .values(&new_player_data)
+            .execute(conn)?;
+        diesel::insert_into(users).values(&new_user).execute(conn)?;
+        record(
+            conn,
+            vec![LedgerEntry::new(
+                new_user.id,
+                Resource::Gold,
+                new_player_data.gold,
+                new_player_data.gold,
+                "register",
+                "starting balance",
+            )],
+        )?;
+        add_item(
+            conn,
+            new_user.id,
+            &item_by_code(conn, STARTING_FOOD)?,
+            10,
+            "register",
+            "starting balance",
+        )?;
+        diesel::delete(invitations.find(&invitation.id)).execute(conn)?;
+
+        let created_user = users.filter(id.eq(&new_user.id)).get_result(conn)?;
+        Ok(created_user)
+    })
 }
 
 pub async fn create_user(
     user: web::Json<NewUser>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, Error> {
-    Ok(web::block(move || query(user.into_inner(), pool))
+    let new_user = user.into_inner().validate()?;
+    let user = web::block(move || query(new_user, pool))
         .await
-        .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
+        .map_err(ServiceError::from)?;
+
+    Ok(HttpResponse::Ok().json(user))
 }
 
-fn query_delete(
-    user_id: uuid::Uuid,
-    pool: web::Data<Pool>,
-) -> Result<String, diesel::result::Error> {
+fn query_delete(user_id: uuid::Uuid, pool: web::Data<Pool>) -> Result<String, ServiceError> {
     use crate::schema::users::dsl::*;
-    let conn: &PgConnection = &pool.get().unwrap();
+    let conn: &PgConnection = &*pool.get()?;
 
-    diesel::delete(users.filter(id.eq(user_id)))
-        .execute(conn)
-        .expect("Error deleting User");
+    let deleted = diesel::delete(users.filter(id.eq(user_id))).execute(conn)?;
+    if deleted == 0 {
+        return Err(ServiceError::NotFound("User not found".to_owned()));
+    }
 
     Ok("Succes".to_owned())
 }
@@ -105,6 +166,5 @@ pub async fn delete_user(
     Ok(web::block(move || query_delete(id.into_inner(), pool))
         .await
         .map(|user| HttpResponse::Ok().json(user))
-        .map_err(|_| HttpResponse::InternalServerError())
-        .unwrap())
+        .map_err(ServiceError::from)?)
 }
@@ -0,0 +1,220 @@
+use actix_web::{web, Error, HttpResponse};
+use chrono::prelude::*;
+use diesel::prelude::*;
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{
+        add_gold, add_item, free_capacity, item_by_code, item_count, load_player_data,
+        lock_inventory,
+    },
+};
+use crate::model::{
+    market::Side,
+    shop::{ShopGood, ShopOrder, ShopTrade},
+};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+    validation::ValidationErrors,
+};
+
+/// shop prices right now and how much more this player may trade today
+#[derive(Debug, Serialize)]
+pub struct ShopListing {
+    pub good: String,
+    pub buy_price: i32,
+    pub sell_price: i32,
+    pub can_buy_today: i32,
+    pub can_sell_today: i32,
+}
+
+// demand is counted over the last day of trades
+const PRICE_WINDOW_HOURS: i64 = 24;
+
+fn traded_since(
+    conn: &PgConnection,
+    shop_good: &str,
+    trade_side: Side,
+    since: NaiveDateTime,
+    player_id: Option<uuid::Uuid>,
+) -> Result<i64, ServiceError> {
+    use crate::schema::shop_trades::dsl::{created_on, good, quantity, shop_trades, side, user_id};
+
+    let trades = shop_trades
+        .filter(good.eq(shop_good))
+        .filter(side.eq(trade_side.as_str()))
+        .filter(created_on.ge(since));
+    let total: Option<i64> = match player_id {
+        Some(player_id) => trades
+            .filter(user_id.eq(player_id))
+            .select(diesel::dsl::sum(quantity))
+            .first(conn)?,
+        None => trades.select(diesel::dsl::sum(quantity)).first(conn)?,
+    };
+    Ok(total.unwrap_or(0))
+}
+
+/// price players pay now, from everyone's buying minus selling in the window
+fn current_price(conn: &PgConnection, shop_good: &ShopGood) -> Result<i32, ServiceError> {
+    let since = chrono::Utc::now().naive_utc() - chrono::Duration::hours(PRICE_WINDOW_HOURS);
+    let bought = traded_since(conn, &shop_good.good, Side::Buy, since, None)?;
+    let sold = traded_since(conn, &shop_good.good, Side::Sell, since, None)?;
+    Ok(shop_good.price(bought - sold))
+}
+
+/// what the player may still trade today (UTC) on one side
+fn remaining_cap(
+    conn: &PgConnection,
+    shop_good: &ShopGood,
+    trade_side: Side,
+    player_id: uuid::Uuid,
+) -> Result<i32, ServiceError> {
+    let today = chrono::Utc::now().date().and_hms(0, 0, 0).naive_utc();
+    let traded = traded_since(conn, &shop_good.good, trade_side, today, Some(player_id))?;
+    Ok((i64::from(shop_good.daily_cap) - traded).max(0) as i32)
+}
+
+fn query_shop(user: LoggedUser, pool: web::Data<Pool>) -> Result<Vec<ShopListing>, ServiceError> {
+    use crate::schema::shop_goods::dsl::{good, shop_goods};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let items = shop_goods.order(good).load::<ShopGood>(conn)?;
+    items
+        .iter()
+        .map(|item| {
+            let price = current_price(conn, item)?;
+            Ok(ShopListing {
+                good: item.good.clone(),
+                buy_price: price,
+                sell_price: ShopGood::buyback(price),
+                can_buy_today: remaining_cap(conn, item, Side::Buy, user.id)?,
+                can_sell_today: remaining_cap(conn, item, Side::Sell, user.id)?,
+            })
+        })
+        .collect()
+}
+
+/// NPC prices and the player's daily allowance
+pub async fn get_shop(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_shop(user, pool))
+        .await
+        .map(|listing| HttpResponse::Ok().json(listing))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_trade(
+    user: LoggedUser,
+    order: ShopOrder,
+    trade_side: Side,
+    pool: web::Data<Pool>,
+) -> Result<ShopTrade, ServiceError> {
+    use crate::schema::shop_goods::dsl::shop_goods;
+    use crate::schema::shop_trades::dsl::shop_trades;
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        let shop_good: ShopGood = shop_goods
+            .find(&order.good)
+            .first(conn)
+            .or_not_found("Shop good")?;
+        let item = item_by_code(conn, &order.good)?;
+
+        if remaining_cap(conn, &shop_good, trade_side, user.id)? < order.quantity {
+            return Err(ServiceError::BadRequest(
+                "Daily shop limit reached".to_owned(),
+            ));
+        }
+
+        let price = current_price(conn, &shop_good)?;
+        let reason = format!("{} {} at the shop", order.quantity, order.good);
+        let price = match trade_side {
+            Side::Buy => {
+                let total = i64::from(price) * i64::from(order.quantity);
+                if i64::from(player_data.gold) < total {
+                    return Err(ServiceError::BadRequest("Not enough gold".to_owned()));
+                }
+                if free_capacity(conn, &inventory)? < order.quantity * item.weight {
+                    return Err(ServiceError::BadRequest(
+                        "Not enough storage space".to_owned(),
+                    ));
+                }
+                add_gold(conn, user.id, -(total as i32), "shop_buy", &reason)?;
+                add_item(conn, user.id, &item, order.quantity, "shop_buy", &reason)?;
+                price
+            }
+            Side::Sell => {
+                if item_count(conn, &inventory, &item)? < order.quantity {
+                    return Err(ServiceError::BadRequest(format!(
+                        "Not enough {}",
+                        order.good
+                    )));
+                }
+                let buyback = ShopGood::buyback(price);
+                add_item(conn, user.id, &item, -order.quantity, "shop_sell", &reason)?;
+                add_gold(
+                    conn,
+                    user.id,
+                    buyback * order.quantity,
+                    "shop_sell",
+                    &reason,
+                )?;
+                buyback
+            }
+        };
+
+        let trade = ShopTrade {
+            id: uuid::Uuid::new_v4(),
+            user_id: user.id,
+            good: order.good.clone(),
+            side: trade_side.as_str().to_owned(),
+            quantity: order.quantity,
+            price,
+            created_on: chrono::Utc::now().naive_utc(),
+        };
+        Ok(diesel::insert_into(shop_trades)
+            .values(&trade)
+            .get_result(conn)?)
+    })
+}
+
+fn validate(order: ShopOrder) -> Result<ShopOrder, ServiceError> {
+    if (1..=10_000).contains(&order.quantity) {
+        return Ok(order);
+    }
+    let mut errors = ValidationErrors::default();
+    errors.add("quantity", "must be between 1 and 10000");
+    Err(ServiceError::Validation(errors))
+}
+
+/// gold => goods from the NPC shop at the current price
+pub async fn shop_buy(
+    user: LoggedUser,
+    order: web::Json<ShopOrder>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let order = validate(order.into_inner())?;
+    Ok(
+        web::block(move || query_trade(user, order, Side::Buy, pool))
+            .await
+            .map(|trade| HttpResponse::Ok().json(trade))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+/// goods => gold from the NPC shop at the buyback price
+pub async fn shop_sell(
+    user: LoggedUser,
+    order: web::Json<ShopOrder>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let order = validate(order.into_inner())?;
+    Ok(
+        web::block(move || query_trade(user, order, Side::Sell, pool))
+            .await
+            .map(|trade| HttpResponse::Ok().json(trade))
+            .map_err(ServiceError::from)?,
+    )
+}
@@ -0,0 +1,139 @@
+use actix_web::{web, Error, HttpResponse};
+use diesel::prelude::*;
+
+use crate::api::{
+    auth::LoggedUser,
+    ledger::record,
+    player::{load_player_data, lock_inventory},
+};
+use crate::model::{
+    ledger::{LedgerEntry, Resource},
+    player::{PlayerData, PlayerInventory},
+    storage::StorageTier,
+};
+use crate::share::{db::Pool, errors::ServiceError};
+
+/// current storage and the next tier, `next` is null at the top level
+#[derive(Debug, Serialize)]
+pub struct StoragePreview {
+    pub level: i32,
+    pub capacity: i32,
+    pub next: Option<StorageTier>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageUpgrade {
+    pub inventory: PlayerInventory,
+    pub gold: i32,
+    pub next: Option<StorageTier>,
+}
+
+fn next_tier(conn: &PgConnection, current: i32) -> Result<Option<StorageTier>, ServiceError> {
+    use crate::schema::storage_tiers::dsl::storage_tiers;
+
+    Ok(storage_tiers
+        .find(current + 1)
+        .first::<StorageTier>(conn)
+        .optional()?)
+}
+
+fn query_preview(user: LoggedUser, pool: web::Data<Pool>) -> Result<StoragePreview, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+
+        Ok(StoragePreview {
+            level: inventory.storage_level,
+            capacity: inventory.capacity,
+            next: next_tier(conn, inventory.storage_level)?,
+        })
+    })
+}
+
+/// what the next storage tier costs and holds
+pub async fn get_storage_upgrade(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_preview(user, pool))
+        .await
+        .map(|preview| HttpResponse::Ok().json(preview))
+        .map_err(ServiceError::from)?)
+}
+
+fn query_upgrade(user: LoggedUser, pool: web::Data<Pool>) -> Result<StorageUpgrade, ServiceError> {
+    use crate::schema::player_inventory::dsl::{
+        capacity, player_inventory, special_currency, storage_level,
+    };
+    use crate::schema::players_data::dsl::{gold, players_data};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+
+        let tier = next_tier(conn, inventory.storage_level)?
+            .ok_or_else(|| ServiceError::BadRequest("Storage is at max level".to_owned()))?;
+        if player_data.gold < tier.gold_cost
+            || inventory.special_currency < tier.special_currency_cost
+        {
+            return Err(ServiceError::BadRequest(
+                "You don't have enough resourses".to_owned(),
+            ));
+        }
+
+        let updated: PlayerData = diesel::update(players_data.find(&player_data.id))
+            .set(gold.eq(gold - tier.gold_cost))
+            .get_result(conn)?;
+        let updated_inventory: PlayerInventory =
+            diesel::update(player_inventory.find(&inventory.id))
+                .set((
+                    capacity.eq(tier.capacity),
+                    storage_level.eq(tier.level),
+                    special_currency.eq(special_currency - tier.special_currency_cost),
+                ))
+                .get_result(conn)?;
+
+        let reason = format!("storage level {}", tier.level);
+        record(
+            conn,
+            vec![
+                LedgerEntry::new(
+                    user.id,
+                    Resource::Gold,
+                    -tier.gold_cost,
+                    updated.gold,
+                    "upgrade_storage",
+                    &reason,
+                ),
+                LedgerEntry::new(
+                    user.id,
+                    Resource::SpecialCurrency,
+                    -tier.special_currency_cost,
+                    updated_inventory.special_currency,
+                    "upgrade_storage",
+                    &reason,
+                ),
+            ],
+        )?;
+
+        Ok(StorageUpgrade {
+            next: next_tier(conn, tier.level)?,
+            inventory: updated_inventory,
+            gold: updated.gold,
+        })
+    })
+}
+
+/// pay gold and special currency => next storage tier
+pub async fn upgrade_storage(
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_upgrade(user, pool))
+        .await
+        .map(|upgrade| HttpResponse::Ok().json(upgrade))
+        .map_err(ServiceError::from)?)
+}
@@ -0,0 +1,163 @@
+use actix_session::Session;
+use actix_web::{web, Error, HttpResponse};
+use chrono;
+use diesel::prelude::*;
+
+use crate::model::refresh_token::RefreshToken;
+use crate::share::{
+    csrf::csrf_cookie,
+    db::Pool,
+    errors::ServiceError,
+    jwt::{create_access_token, ACCESS_TOKEN_MINUTES, REFRESH_TOKEN_DAYS},
+    utils::{hash_token, random_token},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(skip)]
+    pub session_version: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshData {
+    pub refresh_token: String,
+}
+
+/// stores a new refresh token in `family` and signs an access token for it
+fn issue_tokens(
+    user: uuid::Uuid,
+    family: uuid::Uuid,
+    conn: &PgConnection,
+) -> Result<Tokens, ServiceError> {
+    use crate::schema::refresh_tokens::dsl::refresh_tokens;
+    use crate::schema::users::dsl::{session_version, users};
+
+    let version = users.find(user).select(session_version).first(conn)?;
+    let refresh_token = random_token();
+    let now = chrono::Utc::now().naive_utc();
+    let new_token = RefreshToken {
+        id: uuid::Uuid::new_v4(),
+        user_id: user,
+        family_id: family,
+        token_hash: hash_token(&refresh_token),
+        created_on: now,
+        expires_at: now + chrono::Duration::days(*REFRESH_TOKEN_DAYS),
+        revoked: false,
+    };
+
+    diesel::insert_into(refresh_tokens)
+        .values(&new_token)
+        .execute(conn)?;
+
+    Ok(Tokens {
+        access_token: create_access_token(user, version)?,
+        refresh_token,
+        token_type: "Bearer".to_owned(),
+        expires_in: *ACCESS_TOKEN_MINUTES * 60,
+        session_version: version,
+    })
+}
+
+/// tokens for a fresh login start a new family
+pub fn query_new_tokens(user: uuid::Uuid, pool: web::Data<Pool>) -> Result<Tokens, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    issue_tokens(user, uuid::Uuid::new_v4(), conn)
+}
+
+fn query_refresh(token: String, pool: web::Data<Pool>) -> Result<Tokens, ServiceError> {
+    use crate::schema::refresh_tokens::dsl::{family_id, refresh_tokens, revoked, token_hash};
+    let conn: &PgConnection = &*pool.get()?;
+
+    // Ok(None) => reuse detected, family revocation must still be committed
+    let rotated = conn.transaction::<_, ServiceError, _>(|| {
+        let current: RefreshToken = refresh_tokens
+            .filter(token_hash.eq(hash_token(&token)))
+            .for_update()
+            .first(conn)
+            .optional()?
+            .ok_or(ServiceError::Unauthorized)?;
+
+        if current.revoked {
+            diesel::update(refresh_tokens.filter(family_id.eq(current.family_id)))
+                .set(revoked.eq(true))
+                .execute(conn)?;
+            return Ok(None);
+        }
+        if current.expires_at < chrono::Utc::now().naive_utc() {
+            return Err(ServiceError::Unauthorized);
+        }
+
+        diesel::update(refresh_tokens.find(current.id))
+            .set(revoked.eq(true))
+            .execute(conn)?;
+
+        issue_tokens(current.user_id, current.family_id, conn).map(Some)
+    })?;
+
+    rotated.ok_or(ServiceError::Unauthorized)
+}
+
+fn query_revoke(token: String, pool: web::Data<Pool>) -> Result<(), ServiceError> {
+    use crate::schema::refresh_tokens::dsl::{family_id, refresh_tokens, revoked, token_hash};
+    let conn: &PgConnection = &*pool.get()?;
+
+    let family = refresh_tokens
+        .filter(token_hash.eq(hash_token(&token)))
+        .select(family_id)
+        .first::<uuid::Uuid>(conn)
+        .optional()?;
+
+    if let Some(family) = family {
+        diesel::update(refresh_tokens.filter(family_id.eq(family)))
+            .set(revoked.eq(true))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// rotate refresh token => new access + refresh token, old one is revoked
+pub async fn refresh_token(
+    data: web::Json<RefreshData>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let tokens = web::block(move || query_refresh(data.into_inner().refresh_token, pool))
+        .await
+        .map_err(ServiceError::from)?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// revoke refresh token family and drop the session cookie
+pub async fn logout(
+    data: Option<web::Json<RefreshData>>,
+    pool: web::Data<Pool>,
+    session: Session,
+) -> Result<HttpResponse, Error> {
+    if let Some(data) = data {
+        web::block(move || query_revoke(data.into_inner().refresh_token, pool))
+            .await
+            .map_err(ServiceError::from)?;
+    }
+
+    session.purge();
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsrfToken {
+    pub csrf_token: String,
+}
+
+/// (re)issue the csrf cookie, its value goes into the `X-CSRF-Token` header
+pub async fn csrf_token() -> HttpResponse {
+    let token = random_token();
+
+    HttpResponse::Ok()
+        .cookie(csrf_cookie(token.clone()))
+        .json(CsrfToken { csrf_token: token })
+}
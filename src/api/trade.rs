@@ -0,0 +1,532 @@
+use actix_web::{web, Error, HttpResponse};
+use chrono::prelude::*;
+use diesel::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::api::{
+    auth::LoggedUser,
+    player::{
+        add_gold, add_item, add_special_currency, free_capacity, item_by_code, item_count,
+        load_player_data, lock_inventory,
+    },
+};
+use crate::model::{
+    item::Item,
+    player::PlayerData,
+    trade::{NewTrade, TradeBundle, TradeItem, TradeOffer, TradeOfferItem, TradeSide, TradeStatus},
+    user::User,
+};
+use crate::share::{
+    db::Pool,
+    errors::{OrNotFound, ServiceError},
+};
+
+/// an offer as both players see it, with usernames and item codes
+#[derive(Debug, Serialize)]
+pub struct TradeView {
+    pub id: uuid::Uuid,
+    pub from: String,
+    pub to: String,
+    pub offer: TradeBundle,
+    pub request: TradeBundle,
+    pub status: String,
+    pub created_on: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+fn offer_items(
+    conn: &PgConnection,
+    offer: &TradeOffer,
+) -> Result<Vec<(TradeOfferItem, Item)>, ServiceError> {
+    use crate::schema::items::dsl::{code, items};
+    use crate::schema::trade_offer_items::dsl::{trade_offer_id, trade_offer_items};
+
+    Ok(trade_offer_items
+        .inner_join(items)
+        .filter(trade_offer_id.eq(&offer.id))
+        .order(code)
+        .load(conn)?)
+}
+
+fn side_of(lines: &[(TradeOfferItem, Item)], side: TradeSide) -> Vec<(i32, &Item)> {
+    lines
+        .iter()
+        .filter(|(line, _)| line.side == side.as_str())
+        .map(|(line, item)| (line.quantity, item))
+        .collect()
+}
+
+fn view(conn: &PgConnection, offer: TradeOffer) -> Result<TradeView, ServiceError> {
+    use crate::schema::users::dsl::{username, users};
+
+    let lines = offer_items(conn, &offer)?;
+    let bundle = |gold, special_currency, side| TradeBundle {
+        gold,
+        special_currency,
+        items: side_of(&lines, side)
+            .into_iter()
+            .map(|(quantity, item)| TradeItem {
+                item: item.code.clone(),
+                quantity,
+            })
+            .collect(),
+    };
+    let name_of = |user_id: uuid::Uuid| -> Result<String, ServiceError> {
+        users
+            .find(&user_id)
+            .select(username)
+            .first(conn)
+            .or_not_found("Player")
+    };
+
+    Ok(TradeView {
+        id: offer.id,
+        from: name_of(offer.sender_id)?,
+        to: name_of(offer.recipient_id)?,
+        offer: bundle(
+            offer.offered_gold,
+            offer.offered_special_currency,
+            TradeSide::Offered,
+        ),
+        request: bundle(
+            offer.requested_gold,
+            offer.requested_special_currency,
+            TradeSide::Requested,
+        ),
+        status: offer.status,
+        created_on: offer.created_on,
+        expires_at: offer.expires_at,
+        resolved_at: offer.resolved_at,
+    })
+}
+
+/// lock every player in id order so two trades or a trade and a market fill
+/// touching the same players can't deadlock
+fn lock_players(
+    conn: &PgConnection,
+    ids: BTreeSet<uuid::Uuid>,
+) -> Result<BTreeMap<uuid::Uuid, PlayerData>, ServiceError> {
+    let mut players = BTreeMap::new();
+    for id in ids {
+        players.insert(id, load_player_data(conn, id)?);
+    }
+    Ok(players)
+}
+
+fn parties(offer: &TradeOffer) -> BTreeSet<uuid::Uuid> {
+    vec![offer.sender_id, offer.recipient_id]
+        .into_iter()
+        .collect()
+}
+
+/// hand the escrow back to the sender and close the offer with `status`,
+/// `free_capacity` kept the room for the items while the offer was pending,
+/// the caller has both players locked through `lock_players`
+fn close_offer(
+    conn: &PgConnection,
+    offer: &TradeOffer,
+    status: TradeStatus,
+) -> Result<TradeOffer, ServiceError> {
+    use crate::schema::trade_offers::dsl::{resolved_at, status as offer_status, trade_offers};
+
+    let reason = format!("trade offer {} {}", offer.id, status.as_str());
+    add_gold(
+        conn,
+        offer.sender_id,
+        offer.offered_gold,
+        "trade_refund",
+        &reason,
+    )?;
+    add_special_currency(
+        conn,
+        offer.sender_id,
+        offer.offered_special_currency,
+        "trade_refund",
+        &reason,
+    )?;
+    for (quantity, item) in side_of(&offer_items(conn, offer)?, TradeSide::Offered) {
+        add_item(
+            conn,
+            offer.sender_id,
+            item,
+            quantity,
+            "trade_refund",
+            &reason,
+        )?;
+    }
+
+    Ok(diesel::update(trade_offers.find(&offer.id))
+        .set((
+            offer_status.eq(status.as_str()),
+            resolved_at.eq(Some(chrono::Utc::now().naive_utc())),
+        ))
+        .get_result(conn)?)
+}
+
+/// expiry is settled lazily whenever either player looks at their offers
+fn expire_offers(conn: &PgConnection, player_id: uuid::Uuid) -> Result<(), ServiceError> {
+    use crate::schema::trade_offers::dsl::{
+        expires_at, recipient_id, sender_id, status, trade_offers,
+    };
+
+    let expired = trade_offers
+        .filter(sender_id.eq(&player_id).or(recipient_id.eq(&player_id)))
+        .filter(status.eq(TradeStatus::Pending.as_str()))
+        .filter(expires_at.le(chrono::Utc::now().naive_utc()))
+        .for_update()
+        .load::<TradeOffer>(conn)?;
+    lock_players(conn, expired.iter().flat_map(parties).collect())?;
+    for offer in &expired {
+        close_offer(conn, offer, TradeStatus::Expired)?;
+    }
+    Ok(())
+}
+
+/// lock a pending offer the player is part of, `as_sender` picks which part
+fn pending_offer(
+    conn: &PgConnection,
+    player_id: uuid::Uuid,
+    offer_id: uuid::Uuid,
+    as_sender: bool,
+) -> Result<TradeOffer, ServiceError> {
+    use crate::schema::trade_offers::dsl::{recipient_id, sender_id, trade_offers};
+
+    let offer: TradeOffer = match as_sender {
+        true => trade_offers
+            .find(&offer_id)
+            .filter(sender_id.eq(&player_id))
+            .for_update()
+            .first(conn),
+        false => trade_offers
+            .find(&offer_id)
+            .filter(recipient_id.eq(&player_id))
+            .for_update()
+            .first(conn),
+    }
+    .or_not_found("Trade offer")?;
+
+    if offer.status != TradeStatus::Pending.as_str() {
+        return Err(ServiceError::BadRequest(format!(
+            "Trade offer is {}",
+            offer.status
+        )));
+    }
+    Ok(offer)
+}
+
+fn query_create_trade(
+    user: LoggedUser,
+    new_trade: NewTrade,
+    pool: web::Data<Pool>,
+) -> Result<TradeView, ServiceError> {
+    use crate::schema::trade_offer_items::dsl::trade_offer_items;
+    use crate::schema::trade_offers::dsl::trade_offers;
+    use crate::schema::users::dsl::{username, users};
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        let recipient: User = users
+            .filter(username.eq(&new_trade.to))
+            .first(conn)
+            .or_not_found("Recipient")?;
+        if recipient.id == user.id {
+            return Err(ServiceError::BadRequest(
+                "Can't trade with yourself".to_owned(),
+            ));
+        }
+
+        // check everything before anything is taken
+        let player_data = load_player_data(conn, user.id)?;
+        let inventory = lock_inventory(conn, &player_data)?;
+        if player_data.gold < new_trade.offer.gold {
+            return Err(ServiceError::BadRequest("Not enough gold".to_owned()));
+        }
+        if inventory.special_currency < new_trade.offer.special_currency {
+            return Err(ServiceError::BadRequest(
+                "Not enough special_currency".to_owned(),
+            ));
+        }
+        let mut offered = Vec::new();
+        for line in &new_trade.offer.items {
+            let item = item_by_code(conn, &line.item)?;
+            if item_count(conn, &inventory, &item)? < line.quantity {
+                return Err(ServiceError::BadRequest(format!(
+                    "Not enough {}",
+                    item.code
+                )));
+            }
+            offered.push((line.quantity, item));
+        }
+        let mut requested = Vec::new();
+        for line in &new_trade.request.items {
+            requested.push((line.quantity, item_by_code(conn, &line.item)?));
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let offer = TradeOffer {
+            id: uuid::Uuid::new_v4(),
+            sender_id: user.id,
+            recipient_id: recipient.id,
+            offered_gold: new_trade.offer.gold,
+            offered_special_currency: new_trade.offer.special_currency,
+            requested_gold: new_trade.request.gold,
+            requested_special_currency: new_trade.request.special_currency,
+            status: TradeStatus::Pending.as_str().to_owned(),
+            created_on: now,
+            expires_at: now + chrono::Duration::hours(new_trade.expiry_hours()),
+            resolved_at: None,
+        };
+        let offer: TradeOffer = diesel::insert_into(trade_offers)
+            .values(&offer)
+            .get_result(conn)?;
+        let lines: Vec<TradeOfferItem> = offered
+            .iter()
+            .map(|line| (TradeSide::Offered, line))
+            .chain(requested.iter().map(|line| (TradeSide::Requested, line)))
+            .map(|(side, (quantity, item))| TradeOfferItem {
+                id: uuid::Uuid::new_v4(),
+                trade_offer_id: offer.id,
+                item_id: item.id,
+                side: side.as_str().to_owned(),
+                quantity: *quantity,
+            })
+            .collect();
+        diesel::insert_into(trade_offer_items)
+            .values(&lines)
+            .execute(conn)?;
+
+        // the offered half goes into escrow
+        let reason = format!("trade offer {} to {}", offer.id, recipient.username);
+        add_gold(conn, user.id, -offer.offered_gold, "trade_offer", &reason)?;
+        add_special_currency(
+            conn,
+            user.id,
+            -offer.offered_special_currency,
+            "trade_offer",
+            &reason,
+        )?;
+        for (quantity, item) in &offered {
+            add_item(conn, user.id, item, -quantity, "trade_offer", &reason)?;
+        }
+
+        view(conn, offer)
+    })
+}
+
+/// offer goods, gold and special_currency to another player, optionally asking for some back
+pub async fn create_trade(
+    user: LoggedUser,
+    new_trade: web::Json<NewTrade>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    let new_trade = new_trade.into_inner().validate()?;
+    Ok(
+        web::block(move || query_create_trade(user, new_trade, pool))
+            .await
+            .map(|offer| HttpResponse::Ok().json(offer))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+fn query_trades(user: LoggedUser, pool: web::Data<Pool>) -> Result<Vec<TradeView>, ServiceError> {
+    use crate::schema::trade_offers::dsl::{
+        created_on, recipient_id, sender_id, status, trade_offers,
+    };
+    let conn: &PgConnection = &*pool.get()?;
+
+    conn.transaction(|| {
+        expire_offers(conn, user.id)?;
+        trade_offers
+            .filter(sender_id.eq(&user.id).or(recipient_id.eq(&user.id)))
+            .filter(status.eq(TradeStatus::Pending.as_str()))
+            .order(created_on.desc())
+            .load::<TradeOffer>(conn)?
+            .into_iter()
+            .map(|offer| view(conn, offer))
+            .collect()
+    })
+}
+
+/// pending offers the player sent or received, newest first
+pub async fn get_trades(user: LoggedUser, pool: web::Data<Pool>) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || query_trades(user, pool))
+        .await
+        .map(|offers| HttpResponse::Ok().json(offers))
+        .map_err(ServiceError::from)?)
+}
+
+/// move one half of a trade, `from` is empty when it comes out of escrow
+fn transfer(
+    conn: &PgConnection,
+    from: Option<uuid::Uuid>,
+    to: uuid::Uuid,
+    (gold, special_currency): (i32, i32),
+    items: &[(i32, &Item)],
+    reason: &str,
+) -> Result<(), ServiceError> {
+    if let Some(from) = from {
+        add_gold(conn, from, -gold, "trade_accept", reason)?;
+        add_special_currency(conn, from, -special_currency, "trade_accept", reason)?;
+        for (quantity, item) in items {
+            add_item(conn, from, item, -quantity, "trade_accept", reason)?;
+        }
+    }
+    add_gold(conn, to, gold, "trade_accept", reason)?;
+    add_special_currency(conn, to, special_currency, "trade_accept", reason)?;
+    for (quantity, item) in items {
+        add_item(conn, to, item, *quantity, "trade_accept", reason)?;
+    }
+    Ok(())
+}
+
+fn query_accept_trade(
+    user: LoggedUser,
+    offer_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<TradeView, ServiceError> {
+    use crate::schema::trade_offers::dsl::{resolved_at, status, trade_offers};
+    let conn: &PgConnection = &*pool.get()?;
+
+    // committed on its own, an expired offer stays refunded even though accepting fails
+    conn.transaction(|| expire_offers(conn, user.id))?;
+    conn.transaction(|| {
+        let offer = pending_offer(conn, user.id, offer_id, false)?;
+        let now = chrono::Utc::now().naive_utc();
+        if offer.expires_at <= now {
+            return Err(ServiceError::BadRequest("Trade offer expired".to_owned()));
+        }
+
+        let players = lock_players(conn, parties(&offer))?;
+        let sender_data = &players[&offer.sender_id];
+        let recipient_data = &players[&offer.recipient_id];
+        let sender_inventory = lock_inventory(conn, sender_data)?;
+        let recipient_inventory = lock_inventory(conn, recipient_data)?;
+
+        let lines = offer_items(conn, &offer)?;
+        let offered = side_of(&lines, TradeSide::Offered);
+        let requested = side_of(&lines, TradeSide::Requested);
+        let weight = |items: &[(i32, &Item)]| -> i32 {
+            items
+                .iter()
+                .map(|(quantity, item)| quantity * item.weight)
+                .sum()
+        };
+
+        if recipient_data.gold < offer.requested_gold {
+            return Err(ServiceError::BadRequest("Not enough gold".to_owned()));
+        }
+        if recipient_inventory.special_currency < offer.requested_special_currency {
+            return Err(ServiceError::BadRequest(
+                "Not enough special_currency".to_owned(),
+            ));
+        }
+        for (quantity, item) in &requested {
+            if item_count(conn, &recipient_inventory, item)? < *quantity {
+                return Err(ServiceError::BadRequest(format!(
+                    "Not enough {}",
+                    item.code
+                )));
+            }
+        }
+        if free_capacity(conn, &recipient_inventory)? + weight(&requested) < weight(&offered) {
+            return Err(ServiceError::BadRequest(
+                "Not enough storage space".to_owned(),
+            ));
+        }
+        // the sender's escrow still holds its room until it leaves with this accept
+        if free_capacity(conn, &sender_inventory)? + weight(&offered) < weight(&requested) {
+            return Err(ServiceError::BadRequest(
+                "The sender has no storage space left".to_owned(),
+            ));
+        }
+
+        // the requested half moves from the recipient, the escrow is released to them
+        let reason = format!("trade offer {}", offer.id);
+        transfer(
+            conn,
+            Some(offer.recipient_id),
+            offer.sender_id,
+            (offer.requested_gold, offer.requested_special_currency),
+            &requested,
+            &reason,
+        )?;
+        transfer(
+            conn,
+            None,
+            offer.recipient_id,
+            (offer.offered_gold, offer.offered_special_currency),
+            &offered,
+            &reason,
+        )?;
+
+        let offer = diesel::update(trade_offers.find(&offer.id))
+            .set((
+                status.eq(TradeStatus::Accepted.as_str()),
+                resolved_at.eq(Some(now)),
+            ))
+            .get_result(conn)?;
+        view(conn, offer)
+    })
+}
+
+/// recipient takes the deal, both halves move in one transaction or not at all
+pub async fn accept_trade(
+    user: LoggedUser,
+    offer_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(
+        web::block(move || query_accept_trade(user, offer_id.into_inner(), pool))
+            .await
+            .map(|offer| HttpResponse::Ok().json(offer))
+            .map_err(ServiceError::from)?,
+    )
+}
+
+fn query_close_trade(
+    user: LoggedUser,
+    offer_id: uuid::Uuid,
+    status: TradeStatus,
+    pool: web::Data<Pool>,
+) -> Result<TradeView, ServiceError> {
+    let conn: &PgConnection = &*pool.get()?;
+
+    // like accepting, expiry is committed first so only one set of player locks is taken at a time
+    conn.transaction(|| expire_offers(conn, user.id))?;
+    conn.transaction(|| {
+        let as_sender = status == TradeStatus::Cancelled;
+        let offer = pending_offer(conn, user.id, offer_id, as_sender)?;
+        lock_players(conn, parties(&offer))?;
+        let offer = close_offer(conn, &offer, status)?;
+        view(conn, offer)
+    })
+}
+
+/// recipient turns the offer down, the escrow goes back to the sender
+pub async fn reject_trade(
+    user: LoggedUser,
+    offer_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || {
+        query_close_trade(user, offer_id.into_inner(), TradeStatus::Rejected, pool)
+    })
+    .await
+    .map(|offer| HttpResponse::Ok().json(offer))
+    .map_err(ServiceError::from)?)
+}
+
+/// sender takes the offer back with its escrow
+pub async fn cancel_trade(
+    user: LoggedUser,
+    offer_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, Error> {
+    Ok(web::block(move || {
+        query_close_trade(user, offer_id.into_inner(), TradeStatus::Cancelled, pool)
+    })
+    .await
+    .map(|offer| HttpResponse::Ok().json(offer))
+    .map_err(ServiceError::from)?)
+}
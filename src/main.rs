@@ -14,8 +14,8 @@ extern crate serde;
 extern crate serde_json;
 
 use actix_files as fs;
+use actix_session::CookieSession;
 use std::{env, io};
-// use actix_session::{CookieSession, Session};
 //use actix_identity::{CookieIdentityPolicy, IdentityService};
 use actix_web::http::StatusCode;
 use actix_web::{guard, middleware, web, App, HttpRequest, HttpResponse, HttpServer, Result};
@@ -49,8 +49,10 @@ async fn p404() -> Result<fs::NamedFile> {
 
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
+    dotenv::dotenv().ok();
     env::set_var("RUST_LOG", "actix_web=debug");
     env_logger::init();
+    share::utils::check_secret_key()?;
 
     // Start 3 db executor actors
     let manager = ConnectionManager::<PgConnection>::new(dotenv!("DATABASE_URL"));
@@ -58,6 +60,9 @@ async fn main() -> io::Result<()> {
         .build(manager)
         .expect("Failed to create pool.");
 
+    let mailer = share::email::mailer_from_env();
+    let cors_settings = share::cors::CorsSettings::from_env();
+
     // let schema = std::sync::Arc::new(create_schema());
 
     //let domain: String = std::env::var("DOMAIN").unwrap_or_else(|_| "localhost".to_string());
@@ -65,14 +70,15 @@ async fn main() -> io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .data(pool.clone())
-            // .wrap(
-            //     Cors::new()
-            //         .allowed_origin("http://localhost:8080")
-            //         .allowed_methods(vec!["GET", "POST"])
-            //         .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
-            //         .allowed_header(header::CONTENT_TYPE)
-            //         .max_age(3600),
-            // )
+            .data(mailer.clone())
+            .wrap(share::csrf::CsrfProtection)
+            .wrap(
+                CookieSession::private(share::utils::session_key())
+                    .name(share::utils::SESSION_COOKIE)
+                    .path("/")
+                    .secure(false), // set true if https
+            )
+            .wrap(cors_settings.cors())
             .wrap(middleware::Logger::default())
             // .wrap(IdentityService::new(
             //     CookieIdentityPolicy::new(utils::SECRET_KEY.as_bytes())
@@ -87,13 +93,26 @@ async fn main() -> io::Result<()> {
             .configure(router::get_time)
             .configure(router::users)
             .configure(router::user)
+            .configure(router::invitation)
             .configure(router::login)
+            .configure(router::token)
+            .configure(router::password_reset)
+            .configure(router::items)
             .configure(router::factories)
             .configure(router::buy_factories)
+            .configure(router::sell_factories)
             .configure(router::work_factories)
+            .configure(router::production)
             .configure(router::upgrade_factories)
             .configure(router::battle_controller)
+            .configure(router::crafting)
+            .configure(router::market)
+            .configure(router::shop)
+            .configure(router::trade)
+            .configure(router::me)
             .configure(router::storage)
+            .configure(router::energy)
+            .configure(router::ledger)
             // webSockets
             // .service(web::resource("/ws/").route(web::get().to(share::web_sockets::ws_index)))
             // static files
@@ -103,7 +122,7 @@ async fn main() -> io::Result<()> {
                 web::resource("").route(web::get().to(p404)).route(
                     web::route()
                         .guard(guard::Not(guard::Get()))
-                        .to(|| HttpResponse::MethodNotAllowed()),
+                        .to(HttpResponse::MethodNotAllowed),
                 ),
             )
     })
@@ -0,0 +1,60 @@
+use crate::schema::{crafting_jobs, recipe_inputs, recipes};
+use crate::share::{errors::ServiceError, validation::ValidationErrors};
+use chrono::prelude::*;
+use uuid;
+
+/// Turns `recipe_inputs` into `output_quantity` of one item, seeded by migration
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "recipes"]
+pub struct Recipe {
+    pub id: uuid::Uuid,
+    pub code: String,
+    pub name: String,
+    pub output_item_id: uuid::Uuid,
+    pub output_quantity: i32,
+    pub energy_cost: i32,
+    pub gold_cost: i32,
+    pub duration_seconds: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "recipe_inputs"]
+pub struct RecipeInput {
+    pub id: uuid::Uuid,
+    pub recipe_id: uuid::Uuid,
+    pub item_id: uuid::Uuid,
+    pub quantity: i32,
+}
+
+/// Costs are paid when a job starts, the output waits until `ready_at`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "crafting_jobs"]
+pub struct CraftingJob {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub recipe_id: uuid::Uuid,
+    pub batches: i32,
+    pub started_at: NaiveDateTime,
+    pub ready_at: NaiveDateTime,
+    pub collected_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CraftOrder {
+    /// recipe code
+    pub recipe: String,
+    pub batches: i32,
+}
+
+const MAX_BATCHES: i32 = 100;
+
+impl CraftOrder {
+    pub fn validate(self) -> Result<Self, ServiceError> {
+        if (1..=MAX_BATCHES).contains(&self.batches) {
+            return Ok(self);
+        }
+        let mut errors = ValidationErrors::default();
+        errors.add("batches", "must be between 1 and 100");
+        Err(ServiceError::Validation(errors))
+    }
+}
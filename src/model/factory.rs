@@ -9,6 +9,69 @@ pub struct Factory {
     pub gold_per_day: i32,
     pub price: i32,
     pub name: String,
-    pub product: String,
     pub product_amount: i32,
+    pub item_id: uuid::Uuid,
+}
+
+lazy_static::lazy_static! {
+pub static ref FACTORY_REFUND_PERCENT: i32 = std::env::var("FACTORY_REFUND_PERCENT")
+    .ok()
+    .and_then(|percent| percent.parse().ok())
+    .unwrap_or(50);
+}
+
+// every copy already owned makes the next one 15% more expensive
+const PRICE_GROWTH: f64 = 1.15;
+
+impl Factory {
+    /// price of one more of this factory for a player that already owns `owned`
+    pub fn price_for(&self, owned: i32) -> i32 {
+        let price = f64::from(self.price) * PRICE_GROWTH.powi(owned.max(0));
+        price.round().min(f64::from(i32::MAX)) as i32
+    }
+
+    /// gold back for selling one, FACTORY_REFUND_PERCENT of the base price
+    pub fn refund(&self) -> i32 {
+        let percent = i64::from((*FACTORY_REFUND_PERCENT).clamp(0, 100));
+        (i64::from(self.price) * percent / 100) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factory(price: i32) -> Factory {
+        Factory {
+            id: uuid::Uuid::new_v4(),
+            level: 1,
+            gold_per_day: 10,
+            price,
+            name: "Bakery".to_string(),
+            product_amount: 10,
+            item_id: uuid::Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn price_grows_with_every_copy_owned() {
+        let bakery = factory(100);
+        assert_eq!(bakery.price_for(0), 100);
+        assert_eq!(bakery.price_for(1), 115);
+        // 100 × 1.15² = 132.25
+        assert_eq!(bakery.price_for(2), 132);
+        // 100 × 1.15³ = 152.0875
+        assert_eq!(bakery.price_for(3), 152);
+    }
+
+    #[test]
+    fn price_ignores_negative_counts() {
+        assert_eq!(factory(100).price_for(-3), 100);
+    }
+
+    #[test]
+    fn price_caps_at_i32_max() {
+        assert_eq!(factory(1_000_000).price_for(1_000), i32::MAX);
+        assert_eq!(factory(i32::MAX).price_for(1), i32::MAX);
+    }
 }
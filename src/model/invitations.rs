@@ -18,7 +18,13 @@ where
         Invitation {
             id: uuid::Uuid::new_v4(),
             email: email.into(),
-            expires_at: chrono::Local::now().naive_local() + chrono::Duration::hours(24),
+            expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::hours(24),
         }
     }
 }
+
+impl Invitation {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < chrono::Utc::now().naive_utc()
+    }
+}
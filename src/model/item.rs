@@ -0,0 +1,65 @@
+use crate::schema::{items, player_items};
+use crate::share::{errors::ServiceError, validation::ValidationErrors};
+use uuid;
+
+lazy_static::lazy_static! {
+pub static ref FOOD_PER_HOUR: i32 = std::env::var("FOOD_PER_HOUR")
+    .ok()
+    .and_then(|limit| limit.parse().ok())
+    .unwrap_or(10);
+}
+
+// energy one piece of food restores, by quality q1..q5
+const FOOD_ENERGY: [i32; 5] = [10, 20, 35, 50, 75];
+
+/// Catalog entry, `code` (e.g. `food_q1`) is what clients, the market and the ledger use
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "items"]
+pub struct Item {
+    pub id: uuid::Uuid,
+    pub code: String,
+    pub name: String,
+    pub item_type: String,
+    pub quality: i32,
+    /// for display only, capacity counts weight
+    pub stack_size: i32,
+    pub weight: i32,
+}
+
+impl Item {
+    /// energy one piece restores, `None` for anything that isn't food
+    pub fn energy_restore(&self) -> Option<i32> {
+        match self.item_type.as_str() {
+            "food" => FOOD_ENERGY.get((self.quality.max(1) - 1) as usize).copied(),
+            _ => None,
+        }
+    }
+}
+
+/// How many of one item sit in an inventory
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "player_items"]
+pub struct PlayerItem {
+    pub id: uuid::Uuid,
+    pub player_inventory_id: uuid::Uuid,
+    pub item_id: uuid::Uuid,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Meal {
+    /// item code of a food
+    pub item: String,
+    pub quantity: i32,
+}
+
+impl Meal {
+    pub fn validate(self) -> Result<Self, ServiceError> {
+        if (1..=100).contains(&self.quantity) {
+            return Ok(self);
+        }
+        let mut errors = ValidationErrors::default();
+        errors.add("quantity", "must be between 1 and 100");
+        Err(ServiceError::Validation(errors))
+    }
+}
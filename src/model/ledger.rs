@@ -0,0 +1,69 @@
+use crate::schema::ledger_entries;
+use chrono::prelude::*;
+use uuid;
+
+/// What a ledger entry counts, items by their code and factories per factory_id
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resource {
+    Gold,
+    SpecialCurrency,
+    Item(String),
+    Factory(uuid::Uuid),
+}
+
+impl Resource {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Resource::Gold => "gold",
+            Resource::SpecialCurrency => "special_currency",
+            Resource::Item(code) => code,
+            Resource::Factory(_) => "factory",
+        }
+    }
+
+    fn factory_id(&self) -> Option<uuid::Uuid> {
+        match self {
+            Resource::Factory(factory_id) => Some(*factory_id),
+            _ => None,
+        }
+    }
+}
+
+/// One change to a player's balance, rows are never updated or deleted
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "ledger_entries"]
+pub struct LedgerEntry {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub resource: String,
+    pub factory_id: Option<uuid::Uuid>,
+    pub delta: i32,
+    pub balance_after: i32,
+    pub action: String,
+    pub reason: String,
+    pub created_on: NaiveDateTime,
+}
+
+impl LedgerEntry {
+    /// `action` names the endpoint or process, `reason` says why in words
+    pub fn new(
+        user_id: uuid::Uuid,
+        resource: Resource,
+        delta: i32,
+        balance_after: i32,
+        action: &str,
+        reason: &str,
+    ) -> Self {
+        LedgerEntry {
+            id: uuid::Uuid::new_v4(),
+            user_id,
+            resource: resource.as_str().to_owned(),
+            factory_id: resource.factory_id(),
+            delta,
+            balance_after,
+            action: action.to_owned(),
+            reason: reason.to_owned(),
+            created_on: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
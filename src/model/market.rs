@@ -0,0 +1,111 @@
+use crate::schema::market_orders;
+use crate::share::{errors::ServiceError, validation::ValidationErrors};
+use chrono::prelude::*;
+use uuid;
+
+lazy_static::lazy_static! {
+pub static ref MARKET_FEE_PERCENT: i32 = std::env::var("MARKET_FEE_PERCENT")
+    .ok()
+    .and_then(|percent| percent.parse().ok())
+    .unwrap_or(5);
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Open => "open",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Buy orders hold `price × remaining` gold and sell orders hold `remaining` goods
+/// in escrow until they fill or get cancelled
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "market_orders"]
+pub struct MarketOrder {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub side: String,
+    pub good: String,
+    pub price: i32,
+    pub quantity: i32,
+    pub remaining: i32,
+    pub status: String,
+    pub created_on: NaiveDateTime,
+}
+
+/// seller's cut of a trade, MARKET_FEE_PERCENT of it is burned
+pub fn market_fee(gold: i32) -> i32 {
+    let percent = i64::from((*MARKET_FEE_PERCENT).clamp(0, 100));
+    (i64::from(gold) * percent / 100) as i32
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewOrder {
+    pub side: Side,
+    /// item code
+    pub good: String,
+    pub price: i32,
+    pub quantity: i32,
+}
+
+const MAX_PRICE: i32 = 1_000_000;
+const MAX_QUANTITY: i32 = 10_000;
+
+impl NewOrder {
+    pub fn validate(self) -> Result<Self, ServiceError> {
+        let mut errors = ValidationErrors::default();
+
+        if !(1..=MAX_PRICE).contains(&self.price) {
+            errors.add("price", "must be between 1 and 1000000");
+        }
+        if !(1..=MAX_QUANTITY).contains(&self.quantity) {
+            errors.add("quantity", "must be between 1 and 10000");
+        }
+        if self.price.checked_mul(self.quantity).is_none() {
+            errors.add("quantity", "price × quantity is too large");
+        }
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(ServiceError::Validation(errors)),
+        }
+    }
+
+    /// gold a buy order locks up
+    pub fn total(&self) -> i32 {
+        self.price * self.quantity
+    }
+}
@@ -1,4 +1,13 @@
+pub mod crafting;
 pub mod factory;
 pub mod invitations;
+pub mod item;
+pub mod ledger;
+pub mod market;
+pub mod password_reset;
 pub mod player;
+pub mod refresh_token;
+pub mod shop;
+pub mod storage;
+pub mod trade;
 pub mod user;
@@ -0,0 +1,31 @@
+use crate::schema::password_resets;
+use chrono::prelude::*;
+use uuid;
+
+/// One time token, same shape as an invitation but only valid for an hour
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable)]
+#[table_name = "password_resets"]
+pub struct PasswordReset {
+    pub id: uuid::Uuid,
+    pub email: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl<T> From<T> for PasswordReset
+where
+    T: Into<String>,
+{
+    fn from(email: T) -> Self {
+        PasswordReset {
+            id: uuid::Uuid::new_v4(),
+            email: email.into(),
+            expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::hours(1),
+        }
+    }
+}
+
+impl PasswordReset {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < chrono::Utc::now().naive_utc()
+    }
+}
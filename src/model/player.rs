@@ -1,3 +1,4 @@
+use crate::model::factory::Factory;
 use crate::schema::{player_factories, player_inventory, player_stats, players_data};
 use chrono::prelude::*;
 use uuid;
@@ -15,6 +16,88 @@ pub struct PlayerData {
     pub gold_acc: i32,
     pub player_stats_id: uuid::Uuid,
     pub player_inventory_id: uuid::Uuid,
+    pub energy_updated_at: NaiveDateTime,
+}
+
+const MILLIS_PER_DAY: i128 = 24 * 60 * 60 * 1000;
+
+impl PlayerData {
+    /// credit idle gold (`gold_acc` per day) earned since `last_updated`,
+    /// the clock only moves by the time that was paid out so fractions carry over
+    pub fn accrue_gold(&mut self, now: NaiveDateTime) -> bool {
+        let elapsed = i128::from((now - self.last_updated).num_milliseconds());
+        if elapsed <= 0 {
+            return false;
+        }
+        if self.gold_acc <= 0 {
+            // nothing to earn, don't let a later gold_acc pay for this time
+            self.last_updated = now;
+            return true;
+        }
+
+        let rate = i128::from(self.gold_acc);
+        let earned = elapsed * rate / MILLIS_PER_DAY;
+        if earned == 0 {
+            return false;
+        }
+        // round up so the leftover never pays out twice
+        let consumed = (earned * MILLIS_PER_DAY + rate - 1) / rate;
+
+        let total = (i128::from(self.gold) + earned).min(i128::from(i32::MAX));
+        self.gold = total as i32;
+        self.last_updated += chrono::Duration::milliseconds(consumed as i64);
+        true
+    }
+
+    /// refill energy for the time passed since `energy_updated_at`,
+    /// a full bar doesn't bank time so the clock restarts on the next spend
+    pub fn regen_energy(&mut self, stats: &PlayerStats, now: NaiveDateTime) -> bool {
+        let max = stats.max_energy();
+        if self.energy >= max {
+            let changed = self.energy_updated_at != now;
+            self.energy_updated_at = now;
+            return changed;
+        }
+
+        let interval = stats.energy_regen_seconds();
+        let elapsed = (now - self.energy_updated_at).num_seconds();
+        let points = elapsed / interval;
+        if points <= 0 {
+            return false;
+        }
+
+        if i64::from(self.energy) + points >= i64::from(max) {
+            self.energy = max;
+            self.energy_updated_at = now;
+        } else {
+            self.energy += points as i32;
+            self.energy_updated_at += chrono::Duration::seconds(points * interval);
+        }
+        true
+    }
+
+    pub fn energy_status(&self, stats: &PlayerStats) -> EnergyStatus {
+        let max_energy = stats.max_energy();
+        let regen_seconds = stats.energy_regen_seconds();
+        EnergyStatus {
+            energy: self.energy,
+            max_energy,
+            regen_seconds,
+            next_point_at: match self.energy < max_energy {
+                true => Some(self.energy_updated_at + chrono::Duration::seconds(regen_seconds)),
+                false => None,
+            },
+        }
+    }
+}
+
+/// energy as clients see it, `next_point_at` is empty while the bar is full
+#[derive(Clone, Debug, Serialize)]
+pub struct EnergyStatus {
+    pub energy: i32,
+    pub max_energy: i32,
+    pub regen_seconds: i64,
+    pub next_point_at: Option<NaiveDateTime>,
 }
 
 impl Default for PlayerData {
@@ -28,6 +111,7 @@ impl Default for PlayerData {
             gold_acc: 10,
             player_stats_id: uuid::Uuid::new_v4(),
             player_inventory_id: uuid::Uuid::new_v4(),
+            energy_updated_at: chrono::Utc::now().naive_utc(),
         }
     }
 }
@@ -39,6 +123,38 @@ pub struct PlayerFactories {
     pub user_id: uuid::Uuid,
     pub factory_id: uuid::Uuid,
     pub amount: i32,
+    pub produced_at: NaiveDateTime,
+}
+
+impl PlayerFactories {
+    /// goods made since `produced_at` at `product_amount × amount` per day,
+    /// with the time they took so the remainder keeps counting
+    pub fn pending_production(
+        &self,
+        factory: &Factory,
+        now: NaiveDateTime,
+    ) -> (i32, NaiveDateTime) {
+        let elapsed = i128::from((now - self.produced_at).num_milliseconds());
+        let rate = i128::from(factory.product_amount) * i128::from(self.amount);
+        if elapsed <= 0 || rate <= 0 {
+            return (0, now.max(self.produced_at));
+        }
+
+        let units = (elapsed * rate / MILLIS_PER_DAY).min(i128::from(i32::MAX)) as i32;
+        (units, self.time_for(factory, units))
+    }
+
+    /// where the clock stands after `units` more have been taken out, so the
+    /// ones left behind stay pending
+    pub fn time_for(&self, factory: &Factory, units: i32) -> NaiveDateTime {
+        let rate = i128::from(factory.product_amount) * i128::from(self.amount);
+        if units <= 0 || rate <= 0 {
+            return self.produced_at;
+        }
+
+        let consumed = (i128::from(units) * MILLIS_PER_DAY + rate - 1) / rate;
+        self.produced_at + chrono::Duration::milliseconds(consumed as i64)
+    }
 }
 
 impl Default for PlayerFactories {
@@ -48,6 +164,7 @@ impl Default for PlayerFactories {
             user_id: uuid::Uuid::new_v4(),
             factory_id: uuid::Uuid::new_v4(),
             amount: 0,
+            produced_at: chrono::Utc::now().naive_utc(),
         }
     }
 }
@@ -57,9 +174,8 @@ impl Default for PlayerFactories {
 pub struct PlayerInventory {
     pub id: uuid::Uuid,
     pub capacity: i32,
-    pub food_q1: i32,
-    pub weapon_q1: i32,
     pub special_currency: i32,
+    pub storage_level: i32,
 }
 
 impl Default for PlayerInventory {
@@ -67,9 +183,8 @@ impl Default for PlayerInventory {
         PlayerInventory {
             id: uuid::Uuid::new_v4(),
             capacity: 100,
-            food_q1: 10,
-            weapon_q1: 0,
             special_currency: 0,
+            storage_level: 1,
         }
     }
 }
@@ -83,6 +198,25 @@ pub struct PlayerStats {
     pub stamina: i32,
 }
 
+const BASE_MAX_ENERGY: i32 = 100;
+const ENERGY_PER_STAMINA: i32 = 10;
+const BASE_REGEN_SECONDS: i64 = 360;
+const REGEN_SECONDS_PER_STAMINA: i64 = 15;
+const MIN_REGEN_SECONDS: i64 = 60;
+
+impl PlayerStats {
+    /// 100 at stamina 1, +10 for every point after
+    pub fn max_energy(&self) -> i32 {
+        BASE_MAX_ENERGY + ENERGY_PER_STAMINA * (self.stamina.max(1) - 1)
+    }
+
+    /// one point every 6 minutes at stamina 1, 15s faster per point, never under a minute
+    pub fn energy_regen_seconds(&self) -> i64 {
+        let bonus = REGEN_SECONDS_PER_STAMINA * i64::from(self.stamina.max(1) - 1);
+        (BASE_REGEN_SECONDS - bonus).max(MIN_REGEN_SECONDS)
+    }
+}
+
 impl Default for PlayerStats {
     fn default() -> PlayerStats {
         PlayerStats {
@@ -93,3 +227,220 @@ impl Default for PlayerStats {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd(2026, 1, 1).and_hms(0, 0, 0) + chrono::Duration::seconds(seconds)
+    }
+
+    fn player(gold: i32, gold_acc: i32, last_updated: NaiveDateTime) -> PlayerData {
+        PlayerData {
+            gold,
+            gold_acc,
+            last_updated,
+            ..PlayerData::default()
+        }
+    }
+
+    #[test]
+    fn accrue_gold_pays_whole_coins_and_keeps_the_fraction() {
+        // 24 gold a day is one an hour
+        let mut data = player(0, 24, at(0));
+        assert!(data.accrue_gold(at(90 * 60)));
+        assert_eq!(data.gold, 1);
+        assert_eq!(data.last_updated, at(60 * 60));
+
+        // the leftover half hour completes the next coin
+        assert!(data.accrue_gold(at(120 * 60)));
+        assert_eq!(data.gold, 2);
+        assert_eq!(data.last_updated, at(120 * 60));
+    }
+
+    #[test]
+    fn accrue_gold_waits_for_a_whole_coin() {
+        let mut data = player(5, 24, at(0));
+        assert!(!data.accrue_gold(at(59 * 60)));
+        assert_eq!(data.gold, 5);
+        assert_eq!(data.last_updated, at(0));
+    }
+
+    #[test]
+    fn accrue_gold_rounds_the_consumed_time_up() {
+        // 7 a day doesn't divide a day into whole milliseconds
+        let mut data = player(0, 7, at(0));
+        assert!(data.accrue_gold(at(24 * 60 * 60)));
+        assert_eq!(data.gold, 7);
+        assert_eq!(data.last_updated, at(24 * 60 * 60));
+
+        let mut data = player(0, 7, at(0));
+        assert!(data.accrue_gold(at(4 * 60 * 60)));
+        assert_eq!(data.gold, 1);
+        // 86400000 / 7 = 12342857.14.. ms, rounded up
+        assert_eq!(
+            data.last_updated,
+            at(0) + chrono::Duration::milliseconds(12_342_858)
+        );
+    }
+
+    #[test]
+    fn accrue_gold_without_income_moves_the_clock() {
+        let mut data = player(5, 0, at(0));
+        assert!(data.accrue_gold(at(600)));
+        assert_eq!(data.gold, 5);
+        assert_eq!(data.last_updated, at(600));
+    }
+
+    #[test]
+    fn accrue_gold_ignores_time_going_backwards() {
+        let mut data = player(5, 24, at(600));
+        assert!(!data.accrue_gold(at(0)));
+        assert!(!data.accrue_gold(at(600)));
+        assert_eq!(data.gold, 5);
+        assert_eq!(data.last_updated, at(600));
+    }
+
+    #[test]
+    fn accrue_gold_caps_at_i32_max() {
+        let mut data = player(i32::MAX - 1, i32::MAX, at(0));
+        assert!(data.accrue_gold(at(24 * 60 * 60)));
+        assert_eq!(data.gold, i32::MAX);
+    }
+
+    fn energy(energy: i32, energy_updated_at: NaiveDateTime) -> PlayerData {
+        PlayerData {
+            energy,
+            energy_updated_at,
+            ..PlayerData::default()
+        }
+    }
+
+    fn stamina(stamina: i32) -> PlayerStats {
+        PlayerStats {
+            stamina,
+            ..PlayerStats::default()
+        }
+    }
+
+    #[test]
+    fn energy_limits_follow_stamina() {
+        assert_eq!(stamina(1).max_energy(), 100);
+        assert_eq!(stamina(3).max_energy(), 120);
+        assert_eq!(stamina(0).max_energy(), 100);
+        assert_eq!(stamina(1).energy_regen_seconds(), 360);
+        assert_eq!(stamina(3).energy_regen_seconds(), 330);
+        assert_eq!(stamina(100).energy_regen_seconds(), 60);
+    }
+
+    #[test]
+    fn regen_energy_keeps_the_partial_interval() {
+        let mut data = energy(50, at(0));
+        assert!(data.regen_energy(&stamina(1), at(2 * 360 + 100)));
+        assert_eq!(data.energy, 52);
+        assert_eq!(data.energy_updated_at, at(2 * 360));
+    }
+
+    #[test]
+    fn regen_energy_waits_for_a_whole_point() {
+        let mut data = energy(50, at(0));
+        assert!(!data.regen_energy(&stamina(1), at(359)));
+        assert_eq!(data.energy, 50);
+        assert_eq!(data.energy_updated_at, at(0));
+    }
+
+    #[test]
+    fn regen_energy_stops_at_max() {
+        let mut data = energy(98, at(0));
+        assert!(data.regen_energy(&stamina(1), at(10 * 360 + 5)));
+        assert_eq!(data.energy, 100);
+        // a full bar doesn't bank the spare time
+        assert_eq!(data.energy_updated_at, at(10 * 360 + 5));
+    }
+
+    #[test]
+    fn regen_energy_on_a_full_bar_only_moves_the_clock() {
+        let mut data = energy(100, at(0));
+        assert!(data.regen_energy(&stamina(1), at(60)));
+        assert_eq!(data.energy, 100);
+        assert_eq!(data.energy_updated_at, at(60));
+        assert!(!data.regen_energy(&stamina(1), at(60)));
+    }
+
+    #[test]
+    fn energy_status_shows_the_next_point_only_below_max() {
+        let data = energy(50, at(0));
+        assert_eq!(data.energy_status(&stamina(1)).next_point_at, Some(at(360)));
+        let data = energy(100, at(0));
+        assert_eq!(data.energy_status(&stamina(1)).next_point_at, None);
+    }
+
+    fn bakery(amount: i32, produced_at: NaiveDateTime) -> (PlayerFactories, Factory) {
+        let factory = Factory {
+            id: uuid::Uuid::new_v4(),
+            level: 1,
+            gold_per_day: 10,
+            price: 100,
+            name: "Bakery".to_string(),
+            product_amount: 24,
+            item_id: uuid::Uuid::new_v4(),
+        };
+        let owned = PlayerFactories {
+            amount,
+            produced_at,
+            factory_id: factory.id,
+            ..PlayerFactories::default()
+        };
+        (owned, factory)
+    }
+
+    #[test]
+    fn pending_production_counts_whole_units_and_keeps_the_rest() {
+        // 24 a day, 2 owned: one unit every 30 minutes
+        let (owned, factory) = bakery(2, at(0));
+        assert_eq!(
+            owned.pending_production(&factory, at(75 * 60)),
+            (2, at(60 * 60))
+        );
+    }
+
+    #[test]
+    fn pending_production_before_the_first_unit() {
+        let (owned, factory) = bakery(1, at(0));
+        assert_eq!(owned.pending_production(&factory, at(59 * 60)), (0, at(0)));
+    }
+
+    #[test]
+    fn pending_production_with_nothing_made() {
+        let (owned, factory) = bakery(0, at(0));
+        assert_eq!(owned.pending_production(&factory, at(600)), (0, at(600)));
+
+        // a clock ahead of now stays where it is
+        let (owned, factory) = bakery(1, at(600));
+        assert_eq!(owned.pending_production(&factory, at(0)), (0, at(600)));
+    }
+
+    #[test]
+    fn pending_production_rounds_the_consumed_time_up() {
+        let (owned, mut factory) = bakery(1, at(0));
+        factory.product_amount = 7;
+        assert_eq!(
+            owned.pending_production(&factory, at(4 * 60 * 60)),
+            (1, at(0) + chrono::Duration::milliseconds(12_342_858))
+        );
+    }
+
+    #[test]
+    fn time_for_only_covers_the_units_taken() {
+        // 4 made by 2h, room for 1: the clock moves 30 minutes, 3 stay pending
+        let (owned, factory) = bakery(2, at(0));
+        let taken = PlayerFactories {
+            produced_at: owned.time_for(&factory, 1),
+            ..owned.clone()
+        };
+        assert_eq!(taken.produced_at, at(30 * 60));
+        assert_eq!(taken.pending_production(&factory, at(2 * 60 * 60)).0, 3);
+        assert_eq!(owned.time_for(&factory, 0), at(0));
+    }
+}
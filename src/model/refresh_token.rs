@@ -0,0 +1,17 @@
+use crate::schema::refresh_tokens;
+use chrono::prelude::*;
+use uuid;
+
+/// Every rotation keeps the family_id of the token it replaced,
+/// so a reused (already rotated) token can revoke the whole chain
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "refresh_tokens"]
+pub struct RefreshToken {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub family_id: uuid::Uuid,
+    pub token_hash: String,
+    pub created_on: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked: bool,
+}
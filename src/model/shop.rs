@@ -0,0 +1,99 @@
+use crate::schema::{shop_goods, shop_trades};
+use chrono::prelude::*;
+use uuid;
+
+/// What the NPC shop trades, seeded by migration
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "shop_goods"]
+pub struct ShopGood {
+    pub good: String,
+    pub base_price: i32,
+    pub liquidity: i32,
+    pub daily_cap: i32,
+}
+
+// the shop buys back at 80% of what it sells for, so flipping never pays
+const BUYBACK_PERCENT: i64 = 80;
+// prices stay between a quarter and four times the base price
+const MIN_FACTOR: f64 = 0.25;
+const MAX_FACTOR: f64 = 4.0;
+
+impl ShopGood {
+    /// players buying pushes the price up, selling pushes it down,
+    /// `liquidity` units of net demand double (or zero) the base price
+    pub fn price(&self, net_demand: i64) -> i32 {
+        let factor =
+            (1.0 + net_demand as f64 / f64::from(self.liquidity)).clamp(MIN_FACTOR, MAX_FACTOR);
+        ((f64::from(self.base_price) * factor).round() as i32).max(1)
+    }
+
+    /// what the shop pays per unit at `price`
+    pub fn buyback(price: i32) -> i32 {
+        ((i64::from(price) * BUYBACK_PERCENT / 100) as i32).max(1)
+    }
+}
+
+/// One player trade with the shop, `side` is from the player's view
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "shop_trades"]
+pub struct ShopTrade {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub good: String,
+    pub side: String,
+    pub quantity: i32,
+    pub price: i32,
+    pub created_on: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShopOrder {
+    /// item code
+    pub good: String,
+    pub quantity: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bread() -> ShopGood {
+        ShopGood {
+            good: "food_q1".to_string(),
+            base_price: 10,
+            liquidity: 100,
+            daily_cap: 50,
+        }
+    }
+
+    #[test]
+    fn price_moves_with_net_demand() {
+        assert_eq!(bread().price(0), 10);
+        assert_eq!(bread().price(50), 15);
+        assert_eq!(bread().price(100), 20);
+        assert_eq!(bread().price(-50), 5);
+    }
+
+    #[test]
+    fn price_stays_between_a_quarter_and_four_times_base() {
+        assert_eq!(bread().price(-75), 3);
+        assert_eq!(bread().price(-1_000), 3);
+        assert_eq!(bread().price(300), 40);
+        assert_eq!(bread().price(1_000_000), 40);
+    }
+
+    #[test]
+    fn price_never_drops_below_one() {
+        let mut cheap = bread();
+        cheap.base_price = 1;
+        assert_eq!(cheap.price(-1_000), 1);
+    }
+
+    #[test]
+    fn buyback_is_80_percent_and_at_least_one() {
+        assert_eq!(ShopGood::buyback(10), 8);
+        assert_eq!(ShopGood::buyback(11), 8);
+        assert_eq!(ShopGood::buyback(1), 1);
+        assert_eq!(ShopGood::buyback(i32::MAX), 1_717_986_917);
+    }
+}
@@ -0,0 +1,11 @@
+use crate::schema::storage_tiers;
+
+/// Storage upgrade levels, seeded by migration, capacity and price of each step
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "storage_tiers"]
+pub struct StorageTier {
+    pub level: i32,
+    pub capacity: i32,
+    pub gold_cost: i32,
+    pub special_currency_cost: i32,
+}
@@ -0,0 +1,156 @@
+use crate::schema::{trade_offer_items, trade_offers};
+use crate::share::{errors::ServiceError, validation::ValidationErrors};
+use chrono::prelude::*;
+use std::collections::BTreeSet;
+use uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradeStatus {
+    Pending,
+    Accepted,
+    Rejected,
+    Cancelled,
+    Expired,
+}
+
+impl TradeStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TradeStatus::Pending => "pending",
+            TradeStatus::Accepted => "accepted",
+            TradeStatus::Rejected => "rejected",
+            TradeStatus::Cancelled => "cancelled",
+            TradeStatus::Expired => "expired",
+        }
+    }
+}
+
+/// which half of an offer a row belongs to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradeSide {
+    Offered,
+    Requested,
+}
+
+impl TradeSide {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TradeSide::Offered => "offered",
+            TradeSide::Requested => "requested",
+        }
+    }
+}
+
+/// The sender's half sits in escrow while `status` is pending, a gift requests nothing
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "trade_offers"]
+pub struct TradeOffer {
+    pub id: uuid::Uuid,
+    pub sender_id: uuid::Uuid,
+    pub recipient_id: uuid::Uuid,
+    pub offered_gold: i32,
+    pub offered_special_currency: i32,
+    pub requested_gold: i32,
+    pub requested_special_currency: i32,
+    pub status: String,
+    pub created_on: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "trade_offer_items"]
+pub struct TradeOfferItem {
+    pub id: uuid::Uuid,
+    pub trade_offer_id: uuid::Uuid,
+    pub item_id: uuid::Uuid,
+    pub side: String,
+    pub quantity: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TradeItem {
+    /// item code
+    pub item: String,
+    pub quantity: i32,
+}
+
+/// one half of a trade
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TradeBundle {
+    pub gold: i32,
+    pub special_currency: i32,
+    pub items: Vec<TradeItem>,
+}
+
+impl TradeBundle {
+    pub fn is_empty(&self) -> bool {
+        self.gold == 0 && self.special_currency == 0 && self.items.is_empty()
+    }
+
+    fn check(&self, field: &'static str, errors: &mut ValidationErrors) {
+        if !(0..=MAX_AMOUNT).contains(&self.gold) {
+            errors.add(field, "gold must be between 0 and 1000000");
+        }
+        if !(0..=MAX_AMOUNT).contains(&self.special_currency) {
+            errors.add(field, "special_currency must be between 0 and 1000000");
+        }
+        if self.items.len() > MAX_ITEMS {
+            errors.add(field, "at most 20 items");
+        }
+        if self
+            .items
+            .iter()
+            .any(|item| !(1..=MAX_QUANTITY).contains(&item.quantity))
+        {
+            errors.add(field, "item quantity must be between 1 and 10000");
+        }
+        let codes: BTreeSet<&str> = self.items.iter().map(|item| item.item.as_str()).collect();
+        if codes.len() != self.items.len() {
+            errors.add(field, "each item only once");
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewTrade {
+    /// recipient username
+    pub to: String,
+    #[serde(default)]
+    pub offer: TradeBundle,
+    #[serde(default)]
+    pub request: TradeBundle,
+    pub expires_in_hours: Option<i64>,
+}
+
+const MAX_AMOUNT: i32 = 1_000_000;
+const MAX_QUANTITY: i32 = 10_000;
+const MAX_ITEMS: usize = 20;
+// offers live a day unless the sender picks between an hour and a week
+const DEFAULT_EXPIRY_HOURS: i64 = 24;
+const MAX_EXPIRY_HOURS: i64 = 168;
+
+impl NewTrade {
+    pub fn validate(self) -> Result<Self, ServiceError> {
+        let mut errors = ValidationErrors::default();
+
+        self.offer.check("offer", &mut errors);
+        self.request.check("request", &mut errors);
+        if self.offer.is_empty() && self.request.is_empty() {
+            errors.add("offer", "offer or request something");
+        }
+        if !(1..=MAX_EXPIRY_HOURS).contains(&self.expiry_hours()) {
+            errors.add("expires_in_hours", "must be between 1 and 168");
+        }
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(ServiceError::Validation(errors)),
+        }
+    }
+
+    pub fn expiry_hours(&self) -> i64 {
+        self.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS)
+    }
+}
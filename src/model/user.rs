@@ -1,4 +1,8 @@
 use crate::schema::users;
+use crate::share::{
+    errors::ServiceError,
+    validation::{check_password, is_valid_email, normalize_email, ValidationErrors, EMAIL_MAX},
+};
 use chrono::prelude::*;
 use uuid;
 
@@ -8,9 +12,41 @@ pub struct User {
     pub id: uuid::Uuid,
     pub email: String,
     pub username: String,
+    #[serde(skip_serializing)]
     pub password: String,
     pub created_on: NaiveDateTime,
     pub player_data_id: uuid::Uuid,
+    pub session_version: i32,
+    pub role: String,
+}
+
+/// Ordered by privilege, `Player < Moderator < Admin`
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl From<&str> for Role {
+    fn from(role: &str) -> Self {
+        match role {
+            "admin" => Role::Admin,
+            "moderator" => Role::Moderator,
+            _ => Role::Player,
+        }
+    }
 }
 
 impl User {
@@ -25,6 +61,25 @@ pub struct AuthData {
     pub email: String,
     pub password: String,
 }
+
+impl AuthData {
+    pub fn validate(mut self) -> Result<Self, ServiceError> {
+        let mut errors = ValidationErrors::default();
+        self.email = normalize_email(&self.email);
+
+        if self.email.is_empty() {
+            errors.add("email", "is required");
+        }
+        if self.password.is_empty() {
+            errors.add("password", "is required");
+        }
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(ServiceError::Validation(errors)),
+        }
+    }
+}
 // #[derive(Debug, Serialize, Deserialize)]
 // struct UserWithData {
 //     pub id: uuid::Uuid,
@@ -108,14 +163,122 @@ pub struct NewUser {
     pub username: String,
     pub email: String,
     pub password: String,
+    pub invitation_id: uuid::Uuid,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct UserId {
-    pub id: uuid::Uuid,
+// column sizes from the users table
+const USERNAME_MIN: usize = 3;
+const USERNAME_MAX: usize = 50;
+
+impl NewUser {
+    /// normalizes the email and checks every field, all broken rules are reported at once
+    pub fn validate(mut self) -> Result<Self, ServiceError> {
+        let mut errors = ValidationErrors::default();
+        self.email = normalize_email(&self.email);
+        self.username = self.username.trim().to_owned();
+
+        let username_len = self.username.chars().count();
+        if !(USERNAME_MIN..=USERNAME_MAX).contains(&username_len) {
+            errors.add("username", "must be between 3 and 50 characters");
+        }
+        if !self
+            .username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            errors.add("username", "may only contain letters, numbers, '_' and '-'");
+        }
+
+        if self.email.chars().count() > EMAIL_MAX {
+            errors.add("email", "must be at most 80 characters");
+        }
+        if !is_valid_email(&self.email) {
+            errors.add("email", "is not a valid email address");
+        }
+
+        check_password(&self.password, &mut errors);
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(ServiceError::Validation(errors)),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct UserInventoryId {
-    pub inventory_id: uuid::Uuid,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_user(username: &str, email: &str, password: &str) -> NewUser {
+        NewUser {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: password.to_string(),
+            invitation_id: uuid::Uuid::new_v4(),
+        }
+    }
+
+    /// the fields a rejected value was reported for, empty when it passed
+    fn invalid<T>(result: Result<T, ServiceError>) -> String {
+        match result {
+            Ok(_) => String::new(),
+            Err(ServiceError::Validation(errors)) => errors.to_string(),
+            Err(err) => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn new_user_normalizes_email_and_username() {
+        let user = new_user("  player_1 ", " Player@Example.COM ", "secret12")
+            .validate()
+            .unwrap();
+        assert_eq!(user.username, "player_1");
+        assert_eq!(user.email, "player@example.com");
+    }
+
+    #[test]
+    fn new_user_username_bounds() {
+        let long = "x".repeat(USERNAME_MAX);
+        let too_long = "x".repeat(USERNAME_MAX + 1);
+        let cases = [
+            ("abc", ""),
+            ("a-b_c", ""),
+            (long.as_str(), ""),
+            ("ab", "invalid username"),
+            ("   ", "invalid username"),
+            (too_long.as_str(), "invalid username"),
+            ("bad name", "invalid username"),
+            ("bad!", "invalid username"),
+        ];
+        for (username, expected) in &cases {
+            let user = new_user(username, "player@example.com", "secret12");
+            assert_eq!(invalid(user.validate()), *expected, "{:?}", username);
+        }
+    }
+
+    #[test]
+    fn new_user_reports_every_broken_field() {
+        let user = new_user("ab", "not an email", "short");
+        assert_eq!(
+            invalid(user.validate()),
+            "invalid email, password, username"
+        );
+
+        let email = format!("{}@example.com", "x".repeat(EMAIL_MAX));
+        let user = new_user("player", &email, "secret12");
+        assert_eq!(invalid(user.validate()), "invalid email");
+    }
+
+    #[test]
+    fn auth_data_requires_both_fields() {
+        let auth = |email: &str, password: &str| AuthData {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        assert_eq!(invalid(auth("player@example.com", "x").validate()), "");
+        assert_eq!(invalid(auth("  ", "x").validate()), "invalid email");
+        assert_eq!(invalid(auth("", "").validate()), "invalid email, password");
+        let auth = auth(" Player@Example.com", "x").validate().unwrap();
+        assert_eq!(auth.email, "player@example.com");
+    }
 }
@@ -1,72 +1,219 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{guard, web, HttpResponse};
 
+use crate::api::auth::RequireRole;
 use crate::api::battle::battle;
+use crate::api::crafting::{collect_job, craft, get_jobs, get_recipes};
 use crate::api::factories::{
-    add_player_factories, get_factories, get_player_factories, upgrade_factory, work_factory,
+    add_player_factories, demolish_factory, get_factories, sell_factory, upgrade_factory,
+    work_factory,
 };
-use crate::api::login::{get_player_inventory, get_user, login_user};
+use crate::api::invitation::post_invitation;
+use crate::api::items::get_items;
+use crate::api::ledger::{get_ledger, get_user_ledger};
+use crate::api::login::{get_user, login_user};
+use crate::api::market::{cancel_order, get_open_orders, get_order_book, place_order};
+use crate::api::me::{get_me, get_my_factories, get_my_inventory};
+use crate::api::password_reset::{request_reset, reset_password};
+use crate::api::player::{eat, get_energy};
+use crate::api::production::{collect, get_production};
 use crate::api::register::{create_user, delete_user};
+use crate::api::shop::{get_shop, shop_buy, shop_sell};
+use crate::api::storage::{get_storage_upgrade, upgrade_storage};
 use crate::api::time::get_time_handler;
+use crate::api::token::{csrf_token, logout, refresh_token};
+use crate::api::trade::{accept_trade, cancel_trade, create_trade, get_trades, reject_trade};
+use crate::model::user::Role;
+use crate::share::rate_limit::RateLimit;
 
 pub fn users(cfg: &mut web::ServiceConfig) {
+    // listing is ADMIN only, registration stays public
     cfg.service(
+        web::resource("/user")
+            .guard(guard::Get())
+            .wrap(RequireRole(Role::Admin))
+            .route(web::get().to(get_user)),
+    )
+    .service(
         web::resource("/user")
             .data(web::JsonConfig::default().limit(4096))
-            .route(web::get().to(get_user))
             .route(web::post().to(create_user))
-            .route(web::head().to(|| HttpResponse::MethodNotAllowed())),
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
     );
 }
 ///ADMIN
 pub fn user(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/user/{id}")
+            .wrap(RequireRole(Role::Admin))
             .data(web::JsonConfig::default().limit(4096))
             .route(web::delete().to(delete_user))
-            .route(web::head().to(|| HttpResponse::MethodNotAllowed())),
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
     );
 }
 
-pub fn storage(cfg: &mut web::ServiceConfig) {
+pub fn invitation(cfg: &mut web::ServiceConfig) {
     cfg.service(
-        web::resource("/storage")
+        web::resource("/invitation")
+            .wrap(RateLimit::from_env("invitation", 5, 3600))
             .data(web::JsonConfig::default().limit(4096))
-            .route(web::post().to(get_player_inventory))
-            .route(web::head().to(|| HttpResponse::MethodNotAllowed())),
+            .route(web::post().to(post_invitation)),
+    );
+}
+
+pub fn password_reset(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/password/reset")
+            .wrap(RateLimit::from_env("password_reset", 5, 3600))
+            .data(web::JsonConfig::default().limit(4096))
+            .route(web::post().to(request_reset)),
+    )
+    .service(
+        web::resource("/password/reset/{id}")
+            .data(web::JsonConfig::default().limit(4096))
+            .route(web::post().to(reset_password)),
+    );
+}
+
+pub fn me(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/me").route(web::get().to(get_me)))
+        .service(web::resource("/me/inventory").route(web::get().to(get_my_inventory)))
+        .service(web::resource("/me/factories").route(web::get().to(get_my_factories)));
+}
+
+pub fn storage(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/storage/upgrade")
+            .route(web::get().to(get_storage_upgrade))
+            .route(web::post().to(upgrade_storage)),
     );
 }
 
+pub fn ledger(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/ledger").route(web::get().to(get_ledger)))
+        .service(
+            web::resource("/ledger/{user_id}")
+                .wrap(RequireRole(Role::Admin))
+                .route(web::get().to(get_user_ledger)),
+        );
+}
+
+pub fn energy(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/energy").route(web::get().to(get_energy)))
+        .service(
+            web::resource("/energy/eat")
+                .data(web::JsonConfig::default().limit(4096))
+                .route(web::post().to(eat)),
+        );
+}
+
 pub fn login(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/login")
+            .wrap(RateLimit::from_env("login", 10, 60))
             .data(web::JsonConfig::default().limit(4096))
             .route(web::post().to(login_user))
-            .route(web::head().to(|| HttpResponse::MethodNotAllowed())),
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
     );
 }
 
-pub fn factories(cfg: &mut web::ServiceConfig) {
+pub fn token(cfg: &mut web::ServiceConfig) {
     cfg.service(
-        web::resource("/factories")
-            .route(web::get().to(get_factories))
-            .route(web::post().to(get_player_factories)),
-    );
+        web::resource("/token/refresh")
+            .wrap(RateLimit::from_env("token_refresh", 10, 60))
+            .data(web::JsonConfig::default().limit(4096))
+            .route(web::post().to(refresh_token)),
+    )
+    .service(web::resource("/logout").route(web::post().to(logout)))
+    .service(web::resource("/csrf").route(web::get().to(csrf_token)));
+}
+
+pub fn factories(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/factories").route(web::get().to(get_factories)));
+}
+
+pub fn items(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/items").route(web::get().to(get_items)));
 }
 
 pub fn buy_factories(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/buyFactories").route(web::post().to(add_player_factories)));
 }
 
+pub fn sell_factories(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/sellFactory").route(web::post().to(sell_factory)))
+        .service(web::resource("/demolishFactory").route(web::post().to(demolish_factory)));
+}
+
+pub fn production(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/production").route(web::get().to(get_production)))
+        .service(web::resource("/collect").route(web::post().to(collect)));
+}
+
 pub fn work_factories(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/workFactories").route(web::post().to(work_factory)));
+    cfg.service(
+        web::resource("/workFactories")
+            .wrap(RateLimit::from_env("work", 30, 60))
+            .route(web::post().to(work_factory)),
+    );
 }
 
 pub fn upgrade_factories(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/upgradefactory").route(web::post().to(upgrade_factory)));
 }
 
+pub fn crafting(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/recipes").route(web::get().to(get_recipes)))
+        .service(
+            web::resource("/craft")
+                .data(web::JsonConfig::default().limit(4096))
+                .route(web::get().to(get_jobs))
+                .route(web::post().to(craft)),
+        )
+        .service(web::resource("/craft/{id}/collect").route(web::post().to(collect_job)));
+}
+
+pub fn market(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/market/book/{good}").route(web::get().to(get_order_book)))
+        .service(
+            web::resource("/market/orders")
+                .wrap(RateLimit::from_env("market", 30, 60))
+                .data(web::JsonConfig::default().limit(4096))
+                .route(web::get().to(get_open_orders))
+                .route(web::post().to(place_order)),
+        )
+        .service(web::resource("/market/orders/{id}").route(web::delete().to(cancel_order)));
+}
+
+pub fn trade(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/trades")
+            .wrap(RateLimit::from_env("trade", 30, 60))
+            .data(web::JsonConfig::default().limit(16384))
+            .route(web::get().to(get_trades))
+            .route(web::post().to(create_trade)),
+    )
+    .service(web::resource("/trades/{id}").route(web::delete().to(cancel_trade)))
+    .service(web::resource("/trades/{id}/accept").route(web::post().to(accept_trade)))
+    .service(web::resource("/trades/{id}/reject").route(web::post().to(reject_trade)));
+}
+
+pub fn shop(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/shop").route(web::get().to(get_shop)))
+        .service(
+            web::scope("/shop")
+                .wrap(RateLimit::from_env("shop", 30, 60))
+                .data(web::JsonConfig::default().limit(4096))
+                .route("/buy", web::post().to(shop_buy))
+                .route("/sell", web::post().to(shop_sell)),
+        );
+}
+
 pub fn battle_controller(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/battle").route(web::post().to(battle)));
+    cfg.service(
+        web::resource("/battle")
+            .wrap(RateLimit::from_env("battle", 30, 60))
+            .route(web::post().to(battle)),
+    );
 }
 
 pub fn get_time(cfg: &mut web::ServiceConfig) {
@@ -1,3 +1,15 @@
+table! {
+    crafting_jobs (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        recipe_id -> Uuid,
+        batches -> Int4,
+        started_at -> Timestamp,
+        ready_at -> Timestamp,
+        collected_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     factories (id) {
         id -> Uuid,
@@ -5,8 +17,8 @@ table! {
         gold_per_day -> Int4,
         price -> Int4,
         name -> Varchar,
-        product -> Varchar,
         product_amount -> Int4,
+        item_id -> Uuid,
     }
 }
 
@@ -18,12 +30,61 @@ table! {
     }
 }
 
+table! {
+    items (id) {
+        id -> Uuid,
+        code -> Varchar,
+        name -> Varchar,
+        item_type -> Varchar,
+        quality -> Int4,
+        stack_size -> Int4,
+        weight -> Int4,
+    }
+}
+
+table! {
+    ledger_entries (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        resource -> Varchar,
+        factory_id -> Nullable<Uuid>,
+        delta -> Int4,
+        balance_after -> Int4,
+        action -> Varchar,
+        reason -> Varchar,
+        created_on -> Timestamp,
+    }
+}
+
+table! {
+    market_orders (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        side -> Varchar,
+        good -> Varchar,
+        price -> Int4,
+        quantity -> Int4,
+        remaining -> Int4,
+        status -> Varchar,
+        created_on -> Timestamp,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Uuid,
+        email -> Varchar,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     player_factories (id) {
         id -> Uuid,
         user_id -> Uuid,
         factory_id -> Uuid,
         amount -> Int4,
+        produced_at -> Timestamp,
     }
 }
 
@@ -31,9 +92,17 @@ table! {
     player_inventory (id) {
         id -> Uuid,
         capacity -> Int4,
-        food_q1 -> Int4,
-        weapon_q1 -> Int4,
         special_currency -> Int4,
+        storage_level -> Int4,
+    }
+}
+
+table! {
+    player_items (id) {
+        id -> Uuid,
+        player_inventory_id -> Uuid,
+        item_id -> Uuid,
+        quantity -> Int4,
     }
 }
 
@@ -56,6 +125,97 @@ table! {
         gold_acc -> Int4,
         player_stats_id -> Uuid,
         player_inventory_id -> Uuid,
+        energy_updated_at -> Timestamp,
+    }
+}
+
+table! {
+    recipe_inputs (id) {
+        id -> Uuid,
+        recipe_id -> Uuid,
+        item_id -> Uuid,
+        quantity -> Int4,
+    }
+}
+
+table! {
+    recipes (id) {
+        id -> Uuid,
+        code -> Varchar,
+        name -> Varchar,
+        output_item_id -> Uuid,
+        output_quantity -> Int4,
+        energy_cost -> Int4,
+        gold_cost -> Int4,
+        duration_seconds -> Int4,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        token_hash -> Varchar,
+        created_on -> Timestamp,
+        expires_at -> Timestamp,
+        revoked -> Bool,
+    }
+}
+
+table! {
+    shop_goods (good) {
+        good -> Varchar,
+        base_price -> Int4,
+        liquidity -> Int4,
+        daily_cap -> Int4,
+    }
+}
+
+table! {
+    shop_trades (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        good -> Varchar,
+        side -> Varchar,
+        quantity -> Int4,
+        price -> Int4,
+        created_on -> Timestamp,
+    }
+}
+
+table! {
+    storage_tiers (level) {
+        level -> Int4,
+        capacity -> Int4,
+        gold_cost -> Int4,
+        special_currency_cost -> Int4,
+    }
+}
+
+table! {
+    trade_offer_items (id) {
+        id -> Uuid,
+        trade_offer_id -> Uuid,
+        item_id -> Uuid,
+        side -> Varchar,
+        quantity -> Int4,
+    }
+}
+
+table! {
+    trade_offers (id) {
+        id -> Uuid,
+        sender_id -> Uuid,
+        recipient_id -> Uuid,
+        offered_gold -> Int4,
+        offered_special_currency -> Int4,
+        requested_gold -> Int4,
+        requested_special_currency -> Int4,
+        status -> Varchar,
+        created_on -> Timestamp,
+        expires_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
     }
 }
 
@@ -67,21 +227,52 @@ table! {
         password -> Varchar,
         created_on -> Timestamp,
         player_data_id -> Uuid,
+        session_version -> Int4,
+        role -> Varchar,
     }
 }
 
+joinable!(crafting_jobs -> recipes (recipe_id));
+joinable!(crafting_jobs -> users (user_id));
+joinable!(factories -> items (item_id));
+joinable!(market_orders -> users (user_id));
 joinable!(player_factories -> factories (factory_id));
 joinable!(player_factories -> users (user_id));
+joinable!(player_items -> items (item_id));
+joinable!(player_items -> player_inventory (player_inventory_id));
+joinable!(player_inventory -> storage_tiers (storage_level));
 joinable!(players_data -> player_inventory (player_inventory_id));
 joinable!(players_data -> player_stats (player_stats_id));
+joinable!(recipe_inputs -> items (item_id));
+joinable!(recipe_inputs -> recipes (recipe_id));
+joinable!(recipes -> items (output_item_id));
+joinable!(refresh_tokens -> users (user_id));
+joinable!(shop_trades -> shop_goods (good));
+joinable!(shop_trades -> users (user_id));
+joinable!(trade_offer_items -> items (item_id));
+joinable!(trade_offer_items -> trade_offers (trade_offer_id));
 joinable!(users -> players_data (player_data_id));
 
 allow_tables_to_appear_in_same_query!(
+    crafting_jobs,
     factories,
     invitations,
+    items,
+    ledger_entries,
+    market_orders,
+    password_resets,
     player_factories,
     player_inventory,
+    player_items,
     player_stats,
     players_data,
+    recipe_inputs,
+    recipes,
+    refresh_tokens,
+    shop_goods,
+    shop_trades,
+    storage_tiers,
+    trade_offer_items,
+    trade_offers,
     users,
 );
@@ -0,0 +1,50 @@
+use actix_cors::{Cors, CorsFactory};
+
+fn env_list(key: &str, default: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// CORS_* settings, comma separated lists
+#[derive(Clone)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub max_age: usize,
+}
+
+impl CorsSettings {
+    pub fn from_env() -> CorsSettings {
+        CorsSettings {
+            allowed_origins: env_list("CORS_ALLOWED_ORIGINS", "http://localhost:8080"),
+            allowed_methods: env_list("CORS_ALLOWED_METHODS", "GET,POST,DELETE"),
+            allowed_headers: env_list(
+                "CORS_ALLOWED_HEADERS",
+                "Authorization,Accept,Content-Type,X-CSRF-Token",
+            ),
+            max_age: std::env::var("CORS_MAX_AGE")
+                .ok()
+                .and_then(|max_age| max_age.parse().ok())
+                .unwrap_or(3600),
+        }
+    }
+
+    /// credentials are allowed so the browser client can send the session cookie
+    pub fn cors(&self) -> CorsFactory {
+        let cors = self
+            .allowed_origins
+            .iter()
+            .fold(Cors::new(), |cors, origin| cors.allowed_origin(origin));
+
+        cors.allowed_methods(self.allowed_methods.iter().map(String::as_str))
+            .allowed_headers(self.allowed_headers.iter().map(String::as_str))
+            .supports_credentials()
+            .max_age(self.max_age)
+            .finish()
+    }
+}
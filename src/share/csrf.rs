@@ -0,0 +1,93 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Cookie, Method},
+    Error, HttpMessage,
+};
+use constant_time_eq::constant_time_eq;
+use futures::future::{err, ok, FutureExt, LocalBoxFuture, Ready};
+use std::task::{Context, Poll};
+
+use crate::share::{errors::ServiceError, utils::SESSION_COOKIE};
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// readable by the browser client, which echoes it back in `X-CSRF-Token`
+pub fn csrf_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(CSRF_COOKIE, token)
+        .path("/")
+        .http_only(false)
+        .finish()
+}
+
+/// Double submit check: state changing requests authenticated by the session
+/// cookie must send the csrf cookie value again in the `X-CSRF-Token` header.
+/// Bearer token requests are not sent automatically by the browser, so they pass.
+pub struct CsrfProtection;
+
+impl<S, B> Transform<S> for CsrfProtection
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfProtectionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CsrfProtectionMiddleware { service })
+    }
+}
+
+pub struct CsrfProtectionMiddleware<S> {
+    service: S,
+}
+
+fn is_safe(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD || method == Method::OPTIONS
+}
+
+fn has_valid_token(req: &ServiceRequest) -> bool {
+    let header = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match (req.cookie(CSRF_COOKIE), header) {
+        (Some(cookie), Some(header)) => {
+            !header.is_empty() && constant_time_eq(cookie.value().as_bytes(), header.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+impl<S, B> Service for CsrfProtectionMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let cookie_auth = req.cookie(SESSION_COOKIE).is_some()
+            && !req.headers().contains_key(header::AUTHORIZATION);
+
+        if cookie_auth && !is_safe(req.method()) && !has_valid_token(&req) {
+            return err(ServiceError::Forbidden.into()).boxed_local();
+        }
+
+        self.service.call(req).boxed_local()
+    }
+}
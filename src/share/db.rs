@@ -2,3 +2,5 @@ use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
@@ -1,70 +1,232 @@
-// email_service.rs
-// use crate::errors::ServiceError;
-// use crate::models::Invitation;
-// use sparkpost::transmission::{
-//     EmailAddress, Message, Options, Recipient, Transmission, TransmissionResponse,
-// };
-
-// lazy_static::lazy_static! {
-// static ref API_KEY: String = std::env::var("SPARKPOST_API_KEY").expect("SPARKPOST_API_KEY must be set");
-// }
-
-// pub fn send_invitation(invitation: &Invitation) -> Result<(), ServiceError> {
-//     let tm = Transmission::new_eu(API_KEY.as_str());
-//     let sending_email =
-//         std::env::var("SENDING_EMAIL_ADDRESS").expect("SENDING_EMAIL_ADDRESS must be set");
-//     // new email message with sender name and email
-//     let mut email = Message::new(EmailAddress::new(sending_email, "Let's Organise"));
-
-//     let options = Options {
-//         open_tracking: false,
-//         click_tracking: false,
-//         transactional: true,
-//         sandbox: false,
-//         inline_css: false,
-//         start_time: None,
-//     };
-
-//     // recipient from the invitation email
-//     let recipient: Recipient = invitation.email.as_str().into();
-
-//     let email_body = format!(
-//         "Please click on the link below to complete registration. <br/>
-//          <a href=\"http://localhost:3000/register.html?id={}&email={}\">
-//          http://localhost:3030/register</a> <br>
-//          your Invitation expires on <strong>{}</strong>",
-//         invitation.id,
-//         invitation.email,
-//         invitation
-//             .expires_at
-//             .format("%I:%M %p %A, %-d %B, %C%y")
-//             .to_string()
-//     );
-
-//     // complete the email message with details
-//     email
-//         .add_recipient(recipient)
-//         .options(options)
-//         .subject("You have been invited to join Simple-Auth-Server Rust")
-//         .html(email_body);
-
-//     let result = tm.send(&email);
-
-//     // Note that we only print out the error response from email api
-//     match result {
-//         Ok(res) => match res {
-//             TransmissionResponse::ApiResponse(api_res) => {
-//                 println!("API Response: \n {:#?}", api_res);
-//                 Ok(())
-//             }
-//             TransmissionResponse::ApiError(errors) => {
-//                 println!("Response Errors: \n {:#?}", &errors);
-//                 Err(ServiceError::InternalServerError)
-//             }
-//         },
-//         Err(error) => {
-//             println!("Send Email Error: \n {:#?}", error);
-//             Err(ServiceError::InternalServerError)
-//         }
-//     }
-// }
+use lettre::smtp::authentication::Credentials;
+use lettre::{SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::model::{invitations::Invitation, password_reset::PasswordReset};
+use crate::share::errors::ServiceError;
+
+lazy_static::lazy_static! {
+static ref DOMAIN: String = std::env::var("DOMAIN").unwrap_or_else(|_| "http://localhost:8080".to_string());
+}
+
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+}
+
+/// Outgoing mail, picked once at startup by `mailer_from_env`
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), ServiceError>;
+}
+
+pub struct SmtpMailer {
+    pub host: String,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), ServiceError> {
+        let message = EmailBuilder::new()
+            .to(email.to.as_str())
+            .from(self.from.as_str())
+            .subject(email.subject.as_str())
+            .html(email.html.as_str())
+            .build()
+            .map_err(|err| {
+                log::error!("Build email error: {:?}", err);
+                ServiceError::InternalServerError
+            })?;
+
+        let mut transport = SmtpClient::new_simple(&self.host)
+            .map_err(|err| {
+                log::error!("Smtp client error: {:?}", err);
+                ServiceError::InternalServerError
+            })?
+            .credentials(Credentials::new(
+                self.username.clone(),
+                self.password.clone(),
+            ))
+            .transport();
+
+        transport.send(message.into()).map(|_| ()).map_err(|err| {
+            log::error!("Send email error: {:?}", err);
+            ServiceError::InternalServerError
+        })
+    }
+}
+
+/// Local dev: every email is written to `dir` instead of being sent
+pub struct FileMailer {
+    pub dir: PathBuf,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), ServiceError> {
+        let path = self.dir.join(format!("{}.html", uuid::Uuid::new_v4()));
+        let content = format!(
+            "<!-- To: {} -->\n<!-- Subject: {} -->\n{}\n",
+            email.to, email.subject, email.html
+        );
+
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&path, content))
+            .map_err(|err| {
+                log::error!("Write email error: {:?}", err);
+                ServiceError::InternalServerError
+            })
+    }
+}
+
+/// Tests and local dev: every email is kept in memory, read them back with `sent`
+#[derive(Default)]
+pub struct MemoryMailer {
+    emails: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    // only tests read the outbox back
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<Email> {
+        self.emails
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send(&self, email: &Email) -> Result<(), ServiceError> {
+        self.emails
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(email.clone());
+        Ok(())
+    }
+}
+
+/// MAILER=smtp sends through SMTP_HOST, MAILER=memory keeps emails in memory,
+/// anything else writes to MAIL_DIR
+pub fn mailer_from_env() -> Arc<dyn Mailer> {
+    match std::env::var("MAILER").as_ref().map(String::as_str) {
+        Ok("memory") => Arc::new(MemoryMailer::default()),
+        Ok("smtp") => Arc::new(SmtpMailer {
+            host: std::env::var("SMTP_HOST").expect("SMTP_HOST must be set"),
+            username: std::env::var("SMTP_USERNAME").expect("SMTP_USERNAME must be set"),
+            password: std::env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD must be set"),
+            from: std::env::var("SENDING_EMAIL_ADDRESS")
+                .expect("SENDING_EMAIL_ADDRESS must be set"),
+        }),
+        _ => Arc::new(FileMailer {
+            dir: std::env::var("MAIL_DIR")
+                .unwrap_or_else(|_| "mail".to_string())
+                .into(),
+        }),
+    }
+}
+
+/// user input going into an email body
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// a value going into a link's query string, everything but unreserved characters is percent-encoded
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn invitation_email(invitation: &Invitation) -> Email {
+    Email {
+        to: invitation.email.clone(),
+        subject: "You have been invited to join e-tron".to_string(),
+        html: format!(
+            "Please click on the link below to complete registration. <br/>
+             <a href=\"{domain}/register.html?id={id}&email={email}\">
+             {domain}/register</a> <br>
+             your Invitation expires on <strong>{expires}</strong>",
+            domain = escape_html(DOMAIN.as_str()),
+            id = invitation.id,
+            email = encode_query(&invitation.email),
+            expires = invitation.expires_at.format("%I:%M %p %A, %-d %B, %C%y")
+        ),
+    }
+}
+
+pub fn password_reset_email(reset: &PasswordReset) -> Email {
+    Email {
+        to: reset.email.clone(),
+        subject: "Reset your e-tron password".to_string(),
+        html: format!(
+            "Please click on the link below to choose a new password. <br/>
+             <a href=\"{domain}/reset.html?id={id}\">
+             {domain}/reset</a> <br>
+             the link expires on <strong>{expires}</strong>, ignore this email if you did not ask for it",
+            domain = DOMAIN.as_str(),
+            id = reset.id,
+            expires = reset
+                .expires_at
+                .format("%I:%M %p %A, %-d %B, %C%y")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_mailer_keeps_sent_emails() {
+        let mailer = MemoryMailer::default();
+        let invitation = Invitation::from("player@example.com");
+        mailer.send(&invitation_email(&invitation)).unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "player@example.com");
+        assert!(sent[0].html.contains(&invitation.id.to_string()));
+    }
+
+    #[test]
+    fn invitation_email_escapes_the_address() {
+        let invitation = Invitation::from("\"><script>@example.com");
+        let email = invitation_email(&invitation);
+
+        assert!(!email.html.contains("<script>"));
+        assert!(email
+            .html
+            .contains("email=%22%3E%3Cscript%3E%40example.com\""));
+    }
+
+    #[test]
+    fn invitation_link_encodes_the_address() {
+        let invitation = Invitation::from("first+last&x=1#y@example.com");
+        let email = invitation_email(&invitation);
+
+        assert!(email.html.contains(&format!(
+            "/register.html?id={}&email=first%2Blast%26x%3D1%23y%40example.com\"",
+            invitation.id
+        )));
+    }
+}
@@ -1,49 +1,145 @@
-// use actix_web::{error::ResponseError, HttpResponse};
-// use derive_more::Display;
-// use diesel::result::{DatabaseErrorKind, Error as DBError};
-// use std::convert::From;
-// use uuid::ParseError;
-
-// pub enum ServiceError {
-//     #[display(fmt = "Internal Server Error")]
-//     InternalServerError,
-
-//     #[display(fmt = "Bad Request: {}", _0)]
-//     BadRequest(String),
-
-//     #[display(fmt = "Unauthorized")]
-//     Unauthorized,
-// }
-
-// impl ResponseError for ServiceError {
-//     fn error_response(&self) -> HttpResponse {
-//         match self {
-//             ServiceError::InternalServerError => {
-//                 HttpResponse::InternalServerError().json("Internal Server Error, Please try later")
-//             }
-//             ServiceError::BadRequest(ref message) => HttpResponse::BadRequest().json(message),
-//             ServiceError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
-//         }
-//     }
-// }
-
-// impl From<ParseError> for ServiceError {
-//     fn from(_: ParseError) -> ServiceError {
-//         ServiceError::BadRequest("Invalid UUID".into())
-//     }
-// }
-
-// impl From<DBError> for ServiceError {
-//     fn from(error: DBError) -> ServiceError {
-//         match error {
-//             DBError::DatabaseError(kind, info) => {
-//                 if let DatabaseErrorKind::UniqueViolation = kind {
-//                     let message = info.details().unwrap_or_else(|| info.message()).to_string();
-//                     return ServiceError::BadRequest(message);
-//                 }
-//                 ServiceError::InternalServerError
-//             }
-//             _ => ServiceError::InternalServerError,
-//         }
-//     }
-// }
+use actix_web::{
+    error::{BlockingError, ResponseError},
+    http::header,
+    HttpResponse,
+};
+use derive_more::Display;
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DBError};
+use std::convert::From;
+use uuid::ParseError;
+
+use crate::share::validation::ValidationErrors;
+
+#[derive(Debug, Display)]
+pub enum ServiceError {
+    #[display(fmt = "Internal Server Error")]
+    InternalServerError,
+
+    #[display(fmt = "Bad Request: {}", _0)]
+    BadRequest(String),
+
+    #[display(fmt = "Unauthorized")]
+    Unauthorized,
+
+    #[display(fmt = "Forbidden")]
+    Forbidden,
+
+    #[display(fmt = "Not Found: {}", _0)]
+    NotFound(String),
+
+    #[display(fmt = "Conflict: {}", _0)]
+    Conflict(String),
+
+    #[display(fmt = "Validation failed: {}", _0)]
+    Validation(ValidationErrors),
+
+    /// seconds until the client may retry
+    #[display(fmt = "Too Many Requests")]
+    TooManyRequests(u64),
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize)]
+pub struct ErrorBody<'a> {
+    pub error: &'a str,
+}
+
+/// Validation errors also list the broken rules per field
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorBody<'a> {
+    pub error: &'a str,
+    pub fields: &'a ValidationErrors,
+}
+
+impl ResponseError for ServiceError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ServiceError::InternalServerError => {
+                HttpResponse::InternalServerError().json(ErrorBody {
+                    error: "Internal Server Error, Please try later",
+                })
+            }
+            ServiceError::BadRequest(ref message) => {
+                HttpResponse::BadRequest().json(ErrorBody { error: message })
+            }
+            ServiceError::Unauthorized => HttpResponse::Unauthorized().json(ErrorBody {
+                error: "Unauthorized",
+            }),
+            ServiceError::Forbidden => {
+                HttpResponse::Forbidden().json(ErrorBody { error: "Forbidden" })
+            }
+            ServiceError::NotFound(ref message) => {
+                HttpResponse::NotFound().json(ErrorBody { error: message })
+            }
+            ServiceError::Conflict(ref message) => {
+                HttpResponse::Conflict().json(ErrorBody { error: message })
+            }
+            ServiceError::Validation(ref fields) => {
+                HttpResponse::UnprocessableEntity().json(ValidationErrorBody {
+                    error: "Validation failed",
+                    fields,
+                })
+            }
+            ServiceError::TooManyRequests(retry_after) => HttpResponse::TooManyRequests()
+                .header(header::RETRY_AFTER, retry_after.to_string())
+                .json(ErrorBody {
+                    error: "Too Many Requests",
+                }),
+        }
+    }
+}
+
+impl From<ParseError> for ServiceError {
+    fn from(_: ParseError) -> ServiceError {
+        ServiceError::BadRequest("Invalid UUID".into())
+    }
+}
+
+impl From<DBError> for ServiceError {
+    fn from(error: DBError) -> ServiceError {
+        match error {
+            DBError::NotFound => ServiceError::NotFound("Record not found".into()),
+            DBError::DatabaseError(kind, info) => {
+                let message = info.details().unwrap_or_else(|| info.message()).to_string();
+                match kind {
+                    DatabaseErrorKind::UniqueViolation => ServiceError::Conflict(message),
+                    DatabaseErrorKind::ForeignKeyViolation => ServiceError::BadRequest(message),
+                    _ => ServiceError::InternalServerError,
+                }
+            }
+            _ => ServiceError::InternalServerError,
+        }
+    }
+}
+
+impl From<PoolError> for ServiceError {
+    fn from(error: PoolError) -> ServiceError {
+        log::error!("Connection pool error: {}", error);
+        ServiceError::InternalServerError
+    }
+}
+
+impl From<BlockingError<ServiceError>> for ServiceError {
+    fn from(error: BlockingError<ServiceError>) -> ServiceError {
+        match error {
+            BlockingError::Error(service_error) => service_error,
+            BlockingError::Canceled => ServiceError::InternalServerError,
+        }
+    }
+}
+
+/// `.first(conn).or_not_found("Factory")?` answers 404 "Factory not found"
+/// instead of the generic "Record not found"
+pub trait OrNotFound<T> {
+    fn or_not_found(self, what: &str) -> Result<T, ServiceError>;
+}
+
+impl<T> OrNotFound<T> for Result<T, DBError> {
+    fn or_not_found(self, what: &str) -> Result<T, ServiceError> {
+        self.map_err(|error| match error {
+            DBError::NotFound => ServiceError::NotFound(format!("{} not found", what)),
+            error => error.into(),
+        })
+    }
+}
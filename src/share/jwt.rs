@@ -0,0 +1,56 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+
+use crate::share::{errors::ServiceError, utils::SECRET_KEY};
+
+lazy_static::lazy_static! {
+pub static ref ACCESS_TOKEN_MINUTES: i64 = std::env::var("ACCESS_TOKEN_MINUTES")
+    .ok()
+    .and_then(|minutes| minutes.parse().ok())
+    .unwrap_or(15);
+pub static ref REFRESH_TOKEN_DAYS: i64 = std::env::var("REFRESH_TOKEN_DAYS")
+    .ok()
+    .and_then(|days| days.parse().ok())
+    .unwrap_or(30);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: uuid::Uuid,
+    pub ver: i32,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Short lived signed access token, sent as `Authorization: Bearer <token>`
+pub fn create_access_token(
+    user_id: uuid::Uuid,
+    session_version: i32,
+) -> Result<String, ServiceError> {
+    let now = chrono::Utc::now();
+    let claims = Claims {
+        sub: user_id,
+        ver: session_version,
+        iat: now.timestamp(),
+        exp: (now + chrono::Duration::minutes(*ACCESS_TOKEN_MINUTES)).timestamp(),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(SECRET_KEY.as_bytes()),
+    )
+    .map_err(|err| {
+        log::error!("Encode access token error: {}", err);
+        ServiceError::InternalServerError
+    })
+}
+
+pub fn decode_access_token(token: &str) -> Result<Claims, ServiceError> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(SECRET_KEY.as_bytes()),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+    .map_err(|_| ServiceError::Unauthorized)
+}
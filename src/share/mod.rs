@@ -1,4 +1,11 @@
+pub mod cors;
+pub mod csrf;
 pub mod db;
 pub mod email;
 pub mod errors;
+pub mod jwt;
+pub mod pagination;
+pub mod rate_limit;
+pub mod utils;
+pub mod validation;
 pub mod web_sockets;
@@ -0,0 +1,72 @@
+/// `?page=1&per_page=50`, pages start at 1 and hold at most 100 rows,
+/// pages past 1000000 are clamped so the offset can't overflow
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pagination {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 100;
+const MAX_PAGE: i64 = 1_000_000;
+
+impl Pagination {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+
+    pub fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1) * self.per_page()
+    }
+}
+
+/// One page of results with enough to render a pager
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(page: Option<i64>, per_page: Option<i64>) -> Pagination {
+        Pagination { page, per_page }
+    }
+
+    #[test]
+    fn defaults_to_the_first_page() {
+        let pagination = pagination(None, None);
+        assert_eq!(pagination.page(), 1);
+        assert_eq!(pagination.per_page(), DEFAULT_PER_PAGE);
+        assert_eq!(pagination.offset(), 0);
+    }
+
+    #[test]
+    fn offset_skips_earlier_pages() {
+        assert_eq!(pagination(Some(3), Some(20)).offset(), 40);
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        assert_eq!(pagination(Some(0), Some(0)).page(), 1);
+        assert_eq!(pagination(Some(-5), Some(1000)).per_page(), MAX_PER_PAGE);
+        assert_eq!(pagination(Some(-5), None).offset(), 0);
+    }
+
+    #[test]
+    fn huge_page_does_not_overflow_the_offset() {
+        let pagination = pagination(Some(i64::MAX), Some(i64::MAX));
+        assert_eq!(pagination.page(), MAX_PAGE);
+        assert_eq!(pagination.offset(), (MAX_PAGE - 1) * MAX_PER_PAGE);
+    }
+}
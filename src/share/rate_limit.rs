@@ -0,0 +1,350 @@
+use actix_session::UserSession;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures::future::{err, ok, FutureExt, LocalBoxFuture, Ready};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::api::auth::identity;
+use crate::share::errors::ServiceError;
+
+/// past this many entries full buckets and stale failed logins are dropped
+const MAX_ENTRIES: usize = 10_000;
+
+/// carries its route's limits, so pruning doesn't depend on which route triggered it
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    capacity: f64,
+    refill_per_second: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+struct FailedLogins {
+    count: u32,
+    failed_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl FailedLogins {
+    /// no lockout running and no failure within the longest lockout
+    fn is_stale(&self, now: Instant) -> bool {
+        !matches!(self.locked_until, Some(until) if until > now)
+            && now.saturating_duration_since(self.failed_at)
+                >= Duration::from_secs(MAX_LOCKOUT_SECONDS)
+    }
+}
+
+/// `prune_at` grows with the live entries, so a map full of active clients
+/// isn't rescanned on every request
+struct Entries<T> {
+    map: HashMap<String, T>,
+    prune_at: usize,
+}
+
+impl<T> Entries<T> {
+    fn new() -> Self {
+        Entries {
+            map: HashMap::new(),
+            prune_at: MAX_ENTRIES,
+        }
+    }
+
+    fn prune(&mut self, mut keep: impl FnMut(&mut T) -> bool) {
+        if self.map.len() <= self.prune_at {
+            return;
+        }
+        self.map.retain(|_, entry| keep(entry));
+        self.prune_at = MAX_ENTRIES.max(self.map.len() * 2);
+    }
+}
+
+lazy_static::lazy_static! {
+// shared by every worker, keyed by "route|ip:..." or "route|user:..."
+static ref BUCKETS: Mutex<Entries<Bucket>> = Mutex::new(Entries::new());
+static ref FAILED_LOGINS: Mutex<Entries<FailedLogins>> = Mutex::new(Entries::new());
+static ref LOCKOUT_AFTER: u32 = std::env::var("LOGIN_LOCKOUT_AFTER")
+    .ok()
+    .and_then(|count| count.parse().ok())
+    .unwrap_or(5);
+static ref LOCKOUT_SECONDS: u64 = std::env::var("LOGIN_LOCKOUT_SECONDS")
+    .ok()
+    .and_then(|seconds| seconds.parse().ok())
+    .unwrap_or(60);
+}
+
+const MAX_LOCKOUT_SECONDS: u64 = 3600;
+
+/// Token bucket per client IP and per logged in account,
+/// `capacity` requests that refill over `seconds`
+#[derive(Clone)]
+pub struct RateLimit {
+    name: &'static str,
+    capacity: f64,
+    refill_per_second: f64,
+}
+
+impl RateLimit {
+    /// RATE_LIMIT_<NAME>="capacity/seconds" overrides the defaults, e.g. RATE_LIMIT_LOGIN=10/60
+    pub fn from_env(name: &'static str, capacity: u32, seconds: u32) -> RateLimit {
+        let (capacity, seconds) = std::env::var(format!("RATE_LIMIT_{}", name.to_uppercase()))
+            .ok()
+            .and_then(|value| {
+                let mut parts = value.split('/');
+                let capacity = parts.next()?.trim().parse().ok()?;
+                let seconds = parts.next()?.trim().parse().ok()?;
+                Some((capacity, seconds))
+            })
+            .unwrap_or((capacity, seconds));
+
+        RateLimit {
+            name,
+            capacity: f64::from(capacity),
+            refill_per_second: f64::from(capacity) / f64::from(seconds.max(1)),
+        }
+    }
+
+    /// takes a token from every bucket, or returns seconds until all of them have one
+    fn take(&self, keys: &[String], now: Instant) -> Result<(), u64> {
+        let mut buckets = BUCKETS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // a full bucket is the same as a missing one
+        buckets.prune(|bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+
+        let mut wait: f64 = 0.0;
+        for key in keys {
+            let bucket = buckets
+                .map
+                .entry(format!("{}|{}", self.name, key))
+                .or_insert(Bucket {
+                    tokens: self.capacity,
+                    updated: now,
+                    capacity: self.capacity,
+                    refill_per_second: self.refill_per_second,
+                });
+            bucket.refill(now);
+
+            if bucket.tokens < 1.0 {
+                wait = wait.max((1.0 - bucket.tokens) / self.refill_per_second);
+            }
+        }
+        if wait > 0.0 {
+            return Err(wait.ceil() as u64);
+        }
+
+        for key in keys {
+            if let Some(bucket) = buckets.map.get_mut(&format!("{}|{}", self.name, key)) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            limit: self.clone(),
+        })
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limit: RateLimit,
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let mut keys = Vec::new();
+        if let Some(address) = req.peer_addr() {
+            keys.push(format!("ip:{}", address.ip()));
+        }
+        if let Ok((user, _)) = identity(req.headers(), req.get_session()) {
+            keys.push(format!("user:{}", user));
+        }
+
+        if let Err(retry_after) = self.limit.take(&keys, Instant::now()) {
+            return err(ServiceError::TooManyRequests(retry_after).into()).boxed_local();
+        }
+
+        self.service.call(req).boxed_local()
+    }
+}
+
+/// seconds left on the lockout of `email`, if any
+pub fn login_locked(email: &str) -> Option<u64> {
+    let failed = FAILED_LOGINS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = Instant::now();
+
+    failed
+        .map
+        .get(email)
+        .and_then(|entry| entry.locked_until)
+        .filter(|until| *until > now)
+        .map(|until| until.duration_since(now).as_secs().max(1))
+}
+
+/// every failure past LOGIN_LOCKOUT_AFTER doubles the lockout, up to an hour
+pub fn login_failed(email: &str) {
+    let mut failed = FAILED_LOGINS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = Instant::now();
+    failed.prune(|entry| !entry.is_stale(now));
+
+    let entry = failed.map.entry(email.to_string()).or_insert(FailedLogins {
+        count: 0,
+        failed_at: now,
+        locked_until: None,
+    });
+    // an address that stayed quiet past the longest lockout starts over
+    if entry.is_stale(now) {
+        entry.count = 0;
+    }
+
+    entry.count += 1;
+    entry.failed_at = now;
+    if entry.count >= *LOCKOUT_AFTER {
+        let exponent = (entry.count - *LOCKOUT_AFTER).min(16);
+        let seconds = (*LOCKOUT_SECONDS << exponent).min(MAX_LOCKOUT_SECONDS);
+        entry.locked_until = Some(now + Duration::from_secs(seconds));
+    }
+}
+
+pub fn login_succeeded(email: &str) {
+    FAILED_LOGINS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .map
+        .remove(email);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_empties_and_refills() {
+        let limit = RateLimit {
+            name: "test_refill",
+            capacity: 2.0,
+            refill_per_second: 2.0 / 60.0,
+        };
+        let keys = vec!["ip:127.0.0.1".to_string()];
+        let start = Instant::now();
+
+        assert_eq!(limit.take(&keys, start), Ok(()));
+        assert_eq!(limit.take(&keys, start), Ok(()));
+        assert_eq!(limit.take(&keys, start), Err(30));
+        assert_eq!(limit.take(&keys, start + Duration::from_secs(29)), Err(1));
+        assert_eq!(limit.take(&keys, start + Duration::from_secs(30)), Ok(()));
+    }
+
+    #[test]
+    fn every_key_needs_a_token() {
+        let limit = RateLimit {
+            name: "test_keys",
+            capacity: 1.0,
+            refill_per_second: 1.0,
+        };
+        let now = Instant::now();
+        let ip = vec!["ip:10.0.0.1".to_string()];
+        let both = vec!["ip:10.0.0.1".to_string(), "user:someone".to_string()];
+
+        assert_eq!(limit.take(&ip, now), Ok(()));
+        assert_eq!(limit.take(&both, now), Err(1));
+        // the refused request took nothing from the user bucket
+        assert_eq!(limit.take(&["user:someone".to_string()], now), Ok(()));
+    }
+
+    #[test]
+    fn prune_refills_before_dropping_full_buckets() {
+        let start = Instant::now();
+        let bucket = |tokens, capacity| Bucket {
+            tokens,
+            updated: start,
+            capacity,
+            refill_per_second: 1.0,
+        };
+        let mut buckets = Entries::new();
+        buckets.prune_at = 2;
+        buckets.map.insert("idle".to_string(), bucket(0.0, 5.0));
+        buckets.map.insert("busy".to_string(), bucket(0.0, 100.0));
+        buckets.map.insert("full".to_string(), bucket(3.0, 3.0));
+
+        let later = start + Duration::from_secs(10);
+        buckets.prune(|bucket| {
+            bucket.refill(later);
+            !bucket.is_full()
+        });
+
+        let mut left: Vec<&String> = buckets.map.keys().collect();
+        left.sort();
+        assert_eq!(left, vec!["busy"]);
+        assert_eq!(buckets.prune_at, MAX_ENTRIES);
+    }
+
+    #[test]
+    fn failed_logins_go_stale_after_the_longest_lockout() {
+        let now = Instant::now();
+        let entry = FailedLogins {
+            count: 3,
+            failed_at: now,
+            locked_until: Some(now + Duration::from_secs(60)),
+        };
+        assert!(!entry.is_stale(now));
+
+        let quiet = now + Duration::from_secs(MAX_LOCKOUT_SECONDS);
+        assert!(entry.is_stale(quiet));
+    }
+}
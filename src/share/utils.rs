@@ -1,31 +1,71 @@
-//utils.rs
-use crate::errors::ServiceError;
-use argonautica::{Hasher, Verifier};
+use crate::share::errors::ServiceError;
+use constant_time_eq::constant_time_eq;
+use rand::Rng;
+use sha2::{Digest, Sha256};
 
 lazy_static::lazy_static! {
 pub  static ref SECRET_KEY: String = std::env::var("SECRET_KEY").unwrap_or_else(|_| "0123".repeat(8));
 }
 
-// WARNING THIS IS ONLY FOR DEMO PLEASE DO MORE RESEARCH FOR PRODUCTION USE
+/// Salted Argon2id hash, SECRET_KEY is used as the argon2 secret (pepper)
 pub fn hash_password(password: &str) -> Result<String, ServiceError> {
-    Hasher::default()
-        .with_password(password)
-        .with_secret_key(SECRET_KEY.as_str())
-        .hash()
-        .map_err(|err| {
-            dbg!(err);
-            ServiceError::InternalServerError
-        })
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        secret: SECRET_KEY.as_bytes(),
+        ..argon2::Config::default()
+    };
+
+    argon2::hash_encoded(password.as_bytes(), &salt, &config).map_err(|err| {
+        log::error!("Hash password error: {}", err);
+        ServiceError::InternalServerError
+    })
+}
+
+/// Rows created before hashing was introduced still hold the plain password
+pub fn is_hashed(hash: &str) -> bool {
+    hash.starts_with("$argon2")
 }
 
 pub fn verify(hash: &str, password: &str) -> Result<bool, ServiceError> {
-    Verifier::default()
-        .with_hash(hash)
-        .with_password(password)
-        .with_secret_key(SECRET_KEY.as_str())
-        .verify()
-        .map_err(|err| {
-            dbg!(err);
+    if !is_hashed(hash) {
+        return Ok(constant_time_eq(hash.as_bytes(), password.as_bytes()));
+    }
+
+    argon2::verify_encoded_ext(hash, password.as_bytes(), SECRET_KEY.as_bytes(), &[]).map_err(
+        |err| {
+            log::error!("Verify password error: {}", err);
             ServiceError::Unauthorized
-        })
+        },
+    )
+}
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// CookieSession derives its keys from a master key of at least 32 bytes
+pub const SECRET_KEY_MIN: usize = 32;
+
+/// refuse to start with a SECRET_KEY too short to sign sessions and tokens
+pub fn check_secret_key() -> std::io::Result<()> {
+    if SECRET_KEY.len() < SECRET_KEY_MIN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("SECRET_KEY must be at least {} bytes", SECRET_KEY_MIN),
+        ));
+    }
+    Ok(())
+}
+
+pub fn session_key() -> &'static [u8] {
+    SECRET_KEY.as_bytes()
+}
+
+/// Random url safe token, only its sha256 digest gets stored
+pub fn random_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
 }
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Field name => every rule it broke, answered as 422 by `ServiceError::Validation`
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors(BTreeMap<&'static str, Vec<String>>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &'static str, message: &str) {
+        self.0.entry(field).or_default().push(message.to_owned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<&str> = self.0.keys().copied().collect();
+        write!(f, "invalid {}", fields.join(", "))
+    }
+}
+
+// users.email is VARCHAR(80), invitations.email VARCHAR(100)
+pub const EMAIL_MAX: usize = 80;
+
+const PASSWORD_MIN: usize = 8;
+const PASSWORD_MAX: usize = 128;
+
+/// shared by registration and password reset
+pub fn check_password(password: &str, errors: &mut ValidationErrors) {
+    let len = password.chars().count();
+    if !(PASSWORD_MIN..=PASSWORD_MAX).contains(&len) {
+        errors.add("password", "must be between 8 and 128 characters");
+    }
+    if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        errors.add("password", "must contain a letter and a number");
+    }
+}
+
+/// trimmed and lower-cased, emails are compared in this form everywhere
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// one `@`, no whitespace, and a dot inside the domain
+pub fn is_valid_email(email: &str) -> bool {
+    let mut parts = email.split('@');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => {
+            !local.is_empty()
+                && !email.chars().any(char::is_whitespace)
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|label| !label.is_empty())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password_errors(password: &str) -> Vec<String> {
+        let mut errors = ValidationErrors::default();
+        check_password(password, &mut errors);
+        errors.0.remove("password").unwrap_or_default()
+    }
+
+    #[test]
+    fn valid_emails() {
+        for email in &[
+            "player@example.com",
+            "first.last+tag@mail.example.org",
+            "a@b.co",
+        ] {
+            assert!(is_valid_email(email), "{}", email);
+        }
+    }
+
+    #[test]
+    fn invalid_emails() {
+        for email in &[
+            "",
+            "player",
+            "@example.com",
+            "player@",
+            "player@example",
+            "player@example.",
+            "player@.com",
+            "player@@example.com",
+            "pla yer@example.com",
+            "player@example.com ",
+        ] {
+            assert!(!is_valid_email(email), "{:?}", email);
+        }
+    }
+
+    #[test]
+    fn normalize_email_ignores_case_and_surrounding_whitespace() {
+        for email in &[
+            "player@example.com",
+            "Player@Example.COM",
+            "  player@example.com\t",
+            "\n PLAYER@EXAMPLE.COM ",
+        ] {
+            assert_eq!(normalize_email(email), "player@example.com");
+        }
+    }
+
+    #[test]
+    fn password_rules() {
+        let too_short = "must be between 8 and 128 characters";
+        let plain = "must contain a letter and a number";
+        let cases: &[(&str, &[&str])] = &[
+            ("secret12", &[]),
+            ("pässwört1", &[]),
+            ("secret1", &[too_short]),
+            ("12345678", &[plain]),
+            ("password", &[plain]),
+            ("", &[too_short, plain]),
+        ];
+        for (password, expected) in cases {
+            assert_eq!(&password_errors(password), expected, "{:?}", password);
+        }
+        assert!(password_errors(&format!("a1{}", "x".repeat(126))).is_empty());
+        assert_eq!(
+            password_errors(&format!("a1{}", "x".repeat(127))),
+            vec![too_short]
+        );
+    }
+}